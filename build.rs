@@ -1,4 +1,9 @@
 fn main() {
+    // The icon is a Windows resource; there is nothing to embed on other targets.
+    if std::env::var("CARGO_CFG_TARGET_OS").as_deref() != Ok("windows") {
+        return
+    }
+
     let mut res = winres::WindowsResource::new();
     res.set_icon("resources/icon.ico");
     res.compile().unwrap();
@@ -0,0 +1,645 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::DecoderOptions;
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use crate::{Error, Result};
+
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub enum BitDepth {
+    Int8,
+    Int16,
+    Int24,
+    Float32,
+}
+
+impl std::fmt::Display for BitDepth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let label = match self {
+            BitDepth::Int8 => "8 bit",
+            BitDepth::Int16 => "16 bit",
+            BitDepth::Int24 => "24 bit",
+            BitDepth::Float32 => "32 bit float",
+        };
+        write!(f, "{label}")
+    }
+}
+
+/// Which channels of the output file carry the signal; the others are left silent.
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub enum Channels {
+    Mono,
+    Stereo,
+    LeftOnly,
+    RightOnly,
+}
+
+impl std::fmt::Display for Channels {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let label = match self {
+            Channels::Mono => "Mono",
+            Channels::Stereo => "Stereo",
+            Channels::LeftOnly => "Left Only",
+            Channels::RightOnly => "Right Only",
+        };
+        write!(f, "{label}")
+    }
+}
+
+impl Channels {
+    pub fn count(&self) -> u16 {
+        match self {
+            Channels::Mono => 1,
+            Channels::Stereo | Channels::LeftOnly | Channels::RightOnly => 2,
+        }
+    }
+
+    // Gain applied to the signal in each output channel.
+    fn gains(&self) -> &'static [f32] {
+        match self {
+            Channels::Mono => &[1.0],
+            Channels::Stereo => &[1.0, 1.0],
+            Channels::LeftOnly => &[1.0, 0.0],
+            Channels::RightOnly => &[0.0, 1.0],
+        }
+    }
+}
+
+/// Headerless little-endian samples, for piping audio to and from other programs.
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub enum RawFormat {
+    S16Le,
+    F32Le,
+}
+
+impl std::fmt::Display for RawFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let label = match self {
+            RawFormat::S16Le => "s16le",
+            RawFormat::F32Le => "f32le",
+        };
+        write!(f, "{label}")
+    }
+}
+
+/// Which channel of a multi-channel recording is decoded.
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub enum InputChannel {
+    Left,
+    Right,
+    /// Mix of every channel.
+    Sum,
+}
+
+impl std::fmt::Display for InputChannel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let label = match self {
+            InputChannel::Left => "Left",
+            InputChannel::Right => "Right",
+            InputChannel::Sum => "Sum",
+        };
+        write!(f, "{label}")
+    }
+}
+
+impl InputChannel {
+    // Picks the wanted channel out of one frame; a mono file gives its only channel for any choice.
+    fn select(&self, frame: &[f32]) -> f32 {
+        match self {
+            InputChannel::Left => frame[0],
+            InputChannel::Right => frame[frame.len().min(2) - 1],
+            InputChannel::Sum => frame.iter().sum::<f32>() / frame.len() as f32,
+        }
+    }
+}
+
+const WAVE_FORMAT_PCM: u16 = 1;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+fn u16_at(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]])
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+// Fields of the fmt chunk.
+#[derive(Clone, Copy)]
+struct WavFormat {
+    tag: u16,
+    channels: usize,
+    sample_rate: u32,
+    bits: u16,
+    frame_len: usize,
+}
+
+impl WavFormat {
+    fn parse(body: &[u8]) -> Result<Self> {
+        if body.len() < 16 {
+            return Err(unsupported("fmt chunk too short"))
+        }
+        let mut tag = u16_at(body, 0);
+        if tag == WAVE_FORMAT_EXTENSIBLE {
+            if body.len() < 26 {
+                return Err(unsupported("extensible fmt chunk too short"))
+            }
+            // The first two bytes of the sub-format GUID are the actual format tag.
+            tag = u16_at(body, 24);
+        }
+        let format = WavFormat {
+            tag,
+            channels: u16_at(body, 2) as usize,
+            sample_rate: u32_at(body, 4),
+            bits: u16_at(body, 14),
+            frame_len: u16_at(body, 12) as usize,
+        };
+        Ok(format)
+    }
+
+    fn width(&self) -> usize {
+        self.bits.div_ceil(8) as usize
+    }
+
+    // Reads one sample at full scale ±1.0. Samples narrower than their container are left
+    // justified, so scale by the container.
+    fn sample(&self) -> Result<fn(&[u8]) -> f32> {
+        let sample: fn(&[u8]) -> f32 = match (self.tag, self.width()) {
+            (WAVE_FORMAT_PCM, 1) => |b| (b[0] as f32 - 128.0) / 128.0,
+            (WAVE_FORMAT_PCM, 2) => |b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0,
+            (WAVE_FORMAT_PCM, 3) => |b| i32::from_le_bytes([0, b[0], b[1], b[2]]) as f32 / 2_147_483_648.0,
+            (WAVE_FORMAT_PCM, 4) => |b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32 / 2_147_483_648.0,
+            (WAVE_FORMAT_IEEE_FLOAT, 4) => |b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+            (WAVE_FORMAT_IEEE_FLOAT, 8) => |b| f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]) as f32,
+            _ => return Err(Error::UnsupportedWav(format!("format {} with {} bit samples", self.tag, self.bits))),
+        };
+        Ok(sample)
+    }
+}
+
+fn unsupported(reason: &str) -> Error {
+    Error::UnsupportedWav(reason.to_string())
+}
+
+// Walks the chunks of a WAV file `len` bytes long, returning its format with the offset and
+// length of the sample data, without reading the samples themselves.
+fn wav_layout(reader: &mut (impl Read + Seek), len: u64) -> Result<(WavFormat, u64, u64)> {
+    let mut header = [0; 12];
+    if reader.read_exact(&mut header).is_err() || &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+        return Err(unsupported("no RIFF WAVE header"))
+    }
+
+    let mut format = None;
+    let mut data = None;
+    let mut at = 12;
+    while at + 8 <= len {
+        let mut chunk = [0; 8];
+        reader.seek(SeekFrom::Start(at))?;
+        reader.read_exact(&mut chunk)?;
+        let size = u32_at(&chunk, 4) as u64;
+        // Recorders that are stopped abruptly leave the size of the last chunk wrong, so keep to the file.
+        let body_len = u64::min(len, at + 8 + size) - (at + 8);
+
+        if &chunk[0..4] == b"fmt " {
+            // Nothing past the extensible format's sub-format tag is needed.
+            let mut body = Vec::new();
+            reader.by_ref().take(u64::min(body_len, 64)).read_to_end(&mut body)?;
+            format = Some(WavFormat::parse(&body)?);
+        } else if &chunk[0..4] == b"data" {
+            data = Some((at + 8, body_len));
+        }
+        // Chunks are padded to an even length.
+        at += 8 + size + (size & 1);
+    }
+
+    let format = format.ok_or_else(|| unsupported("no fmt chunk"))?;
+    let (offset, data_len) = data.ok_or_else(|| unsupported("no data chunk"))?;
+    if format.bits == 0 || format.frame_len == 0 {
+        return Err(unsupported("zero sized samples"))
+    }
+    if format.channels == 0 || format.frame_len < format.channels * format.width() {
+        return Err(unsupported("inconsistent fmt chunk"))
+    }
+    if format.sample_rate == 0 {
+        return Err(unsupported("sample rate of 0 Hz"))
+    }
+    Ok((format, offset, data_len))
+}
+
+// Format and sample data of a WAV file held in memory.
+fn wav_data(bytes: &[u8]) -> Result<(WavFormat, &[u8])> {
+    let (format, offset, len) = wav_layout(&mut std::io::Cursor::new(bytes), bytes.len() as u64)?;
+    Ok((format, &bytes[offset as usize..(offset + len) as usize]))
+}
+
+/// Sample rate, number of channels and number of frames of a WAV file held in memory.
+pub fn wav_spec(bytes: &[u8]) -> Result<(u32, usize, usize)> {
+    let (format, data) = wav_data(bytes)?;
+    Ok((format.sample_rate, format.channels, data.len() / format.frame_len))
+}
+
+/// Layout of the samples of a WAV file that is read straight from disk.
+pub struct WavStream {
+    pub sample_rate: u32,
+    pub channels: usize,
+    pub frames: u64,
+    /// Bytes in a frame, which holds one sample of `width` bytes per channel in turn.
+    pub frame_len: usize,
+    pub width: usize,
+    /// Reads one sample at full scale ±1.0.
+    pub sample: fn(&[u8]) -> f32,
+}
+
+/// Opens a WAV file too large to hold in memory, returning its layout and a reader over just
+/// its sample data.
+pub fn open_wav(path: &Path) -> Result<(WavStream, impl Read)> {
+    let file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut reader = BufReader::new(file);
+    let (format, offset, data_len) = wav_layout(&mut reader, len)?;
+    let stream = WavStream {
+        sample_rate: format.sample_rate,
+        channels: format.channels,
+        frames: data_len / format.frame_len as u64,
+        frame_len: format.frame_len,
+        width: format.width(),
+        sample: format.sample()?,
+    };
+    reader.seek(SeekFrom::Start(offset))?;
+    Ok((stream, reader.take(data_len)))
+}
+
+/// Passes every frame of a WAV file held in memory to `frame`, one sample per channel at full
+/// scale ±1.0, and returns the sample rate. Handles 8, 16, 24 and 32 bit integer and 32 and
+/// 64 bit float samples, including WAVE_FORMAT_EXTENSIBLE files.
+pub fn for_each_wav_frame(bytes: &[u8], mut frame: impl FnMut(&[f32])) -> Result<u32> {
+    let (format, data) = wav_data(bytes)?;
+    let width = format.width();
+    let sample = format.sample()?;
+
+    let mut values = vec![0.0; format.channels];
+    for bytes in data.chunks_exact(format.frame_len) {
+        for (c, value) in values.iter_mut().enumerate() {
+            *value = sample(&bytes[c * width..(c + 1) * width]);
+        }
+        frame(&values);
+    }
+
+    Ok(format.sample_rate)
+}
+
+/// Reads a WAV file held in memory as mono samples, taking the chosen channel, with its sample rate.
+pub fn parse_wav(bytes: &[u8], channel: &InputChannel) -> Result<(Vec<f32>, u32)> {
+    let mut samples = Vec::new();
+    let sample_rate = for_each_wav_frame(bytes, |frame| samples.push(channel.select(frame)))?;
+    Ok((samples, sample_rate))
+}
+
+/// Reads a WAV file as mono samples, taking the chosen channel of a multi-channel recording, with its sample rate.
+pub fn read_wav(path: &Path, channel: &InputChannel) -> Result<(Vec<f32>, u32)> {
+    parse_wav(&std::fs::read(path)?, channel)
+}
+
+/// Reads one family of recording formats as mono samples, with their sample rate.
+pub trait AudioReader {
+    /// Lowercase file extensions this reader is picked for.
+    fn extensions(&self) -> &'static [&'static str];
+    fn read(&self, path: &Path, channel: &InputChannel) -> Result<(Vec<f32>, u32)>;
+}
+
+pub struct WavReader;
+
+impl AudioReader for WavReader {
+    fn extensions(&self) -> &'static [&'static str] {
+        &["wav"]
+    }
+
+    fn read(&self, path: &Path, channel: &InputChannel) -> Result<(Vec<f32>, u32)> {
+        read_wav(path, channel)
+    }
+}
+
+/// FLAC, Ogg Vorbis and MP3, through the pure Rust symphonia decoders.
+pub struct CompressedReader;
+
+impl AudioReader for CompressedReader {
+    fn extensions(&self) -> &'static [&'static str] {
+        &["flac", "ogg", "oga", "mp3"]
+    }
+
+    fn read(&self, path: &Path, channel: &InputChannel) -> Result<(Vec<f32>, u32)> {
+        let source = MediaSourceStream::new(Box::new(File::open(path)?), Default::default());
+        let mut hint = Hint::new();
+        if let Some(extension) = path.extension().and_then(|ext| ext.to_str()) {
+            hint.with_extension(extension);
+        }
+
+        let probed = symphonia::default::get_probe().format(&hint, source, &FormatOptions::default(), &MetadataOptions::default())?;
+        let mut format = probed.format;
+        let track = format.default_track().ok_or_else(|| Error::UnsupportedAudio(String::from("no audio track")))?;
+        let track_id = track.id;
+        let sample_rate = track.codec_params.sample_rate.filter(|rate| *rate > 0)
+            .ok_or_else(|| Error::UnsupportedAudio(String::from("unknown sample rate")))?;
+        let mut decoder = symphonia::default::get_codecs().make(&track.codec_params, &DecoderOptions::default())?;
+
+        let mut samples = Vec::new();
+        let mut buffer: Option<SampleBuffer<f32>> = None;
+        loop {
+            let packet = match format.next_packet() {
+                Ok(packet) => packet,
+                // The readers report the end of the stream as an unexpected end of file.
+                Err(SymphoniaError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e.into()),
+            };
+            if packet.track_id() != track_id {
+                continue
+            }
+
+            let decoded = match decoder.decode(&packet) {
+                Ok(decoded) => decoded,
+                // A damaged frame only costs its own few milliseconds, so carry on past it.
+                Err(SymphoniaError::DecodeError(_)) => continue,
+                Err(e) => return Err(e.into()),
+            };
+            let spec = *decoded.spec();
+            let channels = spec.channels.count().max(1);
+            if buffer.as_ref().is_some_and(|buffer| buffer.capacity() < decoded.capacity() * channels) {
+                buffer = None;
+            }
+            let buffer = buffer.get_or_insert_with(|| SampleBuffer::new(decoded.capacity() as u64, spec));
+            buffer.copy_interleaved_ref(decoded);
+            samples.extend(buffer.samples().chunks(channels).map(|frame| channel.select(frame)));
+        }
+
+        Ok((samples, sample_rate))
+    }
+}
+
+/// Every reader, in the order their extensions are offered.
+pub fn readers() -> Vec<Box<dyn AudioReader>> {
+    vec![Box::new(WavReader), Box::new(CompressedReader)]
+}
+
+/// Extensions of every recording format that can be decoded.
+pub fn audio_extensions() -> Vec<&'static str> {
+    readers().iter().flat_map(|reader| reader.extensions().iter().copied()).collect()
+}
+
+/// Reads a recording in any supported format, choosing the reader by extension and
+/// falling back to WAV when the extension is not recognised.
+pub fn read_audio(path: &Path, channel: &InputChannel) -> Result<(Vec<f32>, u32)> {
+    let extension = path.extension().map(|ext| ext.to_string_lossy().to_ascii_lowercase()).unwrap_or_default();
+    let reader = readers().into_iter()
+        .find(|reader| reader.extensions().contains(&extension.as_str()))
+        .unwrap_or_else(|| Box::new(WavReader));
+    reader.read(path, channel)
+}
+
+/// Writes `samples` (full scale at ±1.0) as a WAV file in the requested format, as they are produced.
+pub fn write_wav(path: &Path, samples: impl IntoIterator<Item = f32>, sample_rate: u32, bit_depth: &BitDepth, channels: &Channels) -> Result<()> {
+    write_wav_to(BufWriter::new(File::create(path)?), samples, sample_rate, bit_depth, channels)
+}
+
+/// Writes `samples` as WAV to any seekable writer, which the header's lengths are filled in through
+/// once the last sample is written.
+pub fn write_wav_to(writer: impl Write + Seek, samples: impl IntoIterator<Item = f32>, sample_rate: u32, bit_depth: &BitDepth, channels: &Channels) -> Result<()> {
+    let (bits_per_sample, sample_format) = match bit_depth {
+        BitDepth::Int8 => (8, hound::SampleFormat::Int),
+        BitDepth::Int16 => (16, hound::SampleFormat::Int),
+        BitDepth::Int24 => (24, hound::SampleFormat::Int),
+        BitDepth::Float32 => (32, hound::SampleFormat::Float),
+    };
+
+    let wave_spec = hound::WavSpec {
+        channels: channels.count(),
+        sample_rate,
+        bits_per_sample,
+        sample_format,
+    };
+
+    let mut writer = hound::WavWriter::new(writer, wave_spec)?;
+    for sample in samples {
+        let sample = sample.clamp(-1.0, 1.0);
+        for gain in channels.gains() {
+            let value = sample * gain;
+            match bit_depth {
+                BitDepth::Int8 => writer.write_sample((value * i8::MAX as f32) as i8)?,
+                BitDepth::Int16 => writer.write_sample((value * i16::MAX as f32) as i16)?,
+                BitDepth::Int24 => writer.write_sample((value * 8_388_607.0) as i32)?,
+                BitDepth::Float32 => writer.write_sample(value)?,
+            }
+        }
+    }
+    Ok(writer.finalize()?)
+}
+
+/// Writes `samples` (full scale at ±1.0) as raw PCM, e.g. to stdout or a named pipe, as they are produced.
+pub fn write_raw(writer: impl Write, samples: impl IntoIterator<Item = f32>, format: &RawFormat, channels: &Channels) -> Result<()> {
+    let mut writer = BufWriter::new(writer);
+    for sample in samples {
+        let sample = sample.clamp(-1.0, 1.0);
+        for gain in channels.gains() {
+            let value = sample * gain;
+            match format {
+                RawFormat::S16Le => writer.write_all(&((value * i16::MAX as f32) as i16).to_le_bytes())?,
+                RawFormat::F32Le => writer.write_all(&value.to_le_bytes())?,
+            }
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Reads mono raw PCM until the end of the stream; a trailing partial sample is dropped.
+pub fn read_raw(mut reader: impl Read, format: &RawFormat) -> Result<Vec<f32>> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+
+    let samples = match format {
+        RawFormat::S16Le => bytes.chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 / i16::MAX as f32)
+            .collect(),
+        RawFormat::F32Le => bytes.chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect(),
+    };
+    Ok(samples)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A WAV file with a single chunk of sample data; `extensible` wraps the tag in WAVE_FORMAT_EXTENSIBLE.
+    fn wav_bytes(tag: u16, channels: u16, bits: u16, extensible: bool, data: &[u8]) -> Vec<u8> {
+        let frame_len = channels * bits.div_ceil(8);
+        let mut fmt = Vec::new();
+        fmt.extend((if extensible { WAVE_FORMAT_EXTENSIBLE } else { tag }).to_le_bytes());
+        fmt.extend(channels.to_le_bytes());
+        fmt.extend(8000u32.to_le_bytes());
+        fmt.extend((8000 * frame_len as u32).to_le_bytes());
+        fmt.extend(frame_len.to_le_bytes());
+        fmt.extend(bits.to_le_bytes());
+        if extensible {
+            fmt.extend(22u16.to_le_bytes());
+            fmt.extend(bits.to_le_bytes());
+            fmt.extend(0u32.to_le_bytes());
+            fmt.extend(tag.to_le_bytes());
+            fmt.extend([0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xAA, 0x00, 0x38, 0x9B, 0x71]);
+        }
+
+        let mut bytes = b"RIFF".to_vec();
+        bytes.extend((4 + 8 + fmt.len() as u32 + 8 + data.len() as u32).to_le_bytes());
+        bytes.extend(b"WAVEfmt ");
+        bytes.extend((fmt.len() as u32).to_le_bytes());
+        bytes.extend(fmt);
+        bytes.extend(b"data");
+        bytes.extend((data.len() as u32).to_le_bytes());
+        bytes.extend(data);
+        bytes
+    }
+
+    #[test]
+    fn every_sample_format_reads_at_the_same_scale() {
+        // Half of full scale, positive then negative, in each encoding.
+        let cases: [(u16, u16, Vec<u8>); 6] = [
+            (WAVE_FORMAT_PCM, 8, vec![192, 64]),
+            (WAVE_FORMAT_PCM, 16, [16384i16, -16384].iter().flat_map(|v| v.to_le_bytes()).collect()),
+            (WAVE_FORMAT_PCM, 24, vec![0x00, 0x00, 0x40, 0x00, 0x00, 0xC0]),
+            (WAVE_FORMAT_PCM, 32, [1i32 << 30, -(1 << 30)].iter().flat_map(|v| v.to_le_bytes()).collect()),
+            (WAVE_FORMAT_IEEE_FLOAT, 32, [0.5f32, -0.5].iter().flat_map(|v| v.to_le_bytes()).collect()),
+            (WAVE_FORMAT_IEEE_FLOAT, 64, [0.5f64, -0.5].iter().flat_map(|v| v.to_le_bytes()).collect()),
+        ];
+
+        for (tag, bits, data) in cases {
+            for extensible in [false, true] {
+                let (samples, rate) = parse_wav(&wav_bytes(tag, 1, bits, extensible, &data), &InputChannel::Sum).unwrap();
+                assert_eq!(rate, 8000);
+                assert_eq!(samples, vec![0.5, -0.5], "format {tag}, {bits} bits, extensible {extensible}");
+            }
+        }
+    }
+
+    #[test]
+    fn zero_sized_samples_are_refused() {
+        let mut bytes = wav_bytes(WAVE_FORMAT_PCM, 1, 16, false, &[0; 8]);
+        // Block align and bits per sample, at offsets 12 and 14 of the fmt chunk body.
+        bytes[32..36].fill(0);
+        assert!(matches!(wav_spec(&bytes), Err(Error::UnsupportedWav(_))));
+        assert!(matches!(parse_wav(&bytes, &InputChannel::Sum), Err(Error::UnsupportedWav(_))));
+    }
+
+    #[test]
+    fn every_bit_depth_and_channel_layout_reads_back() {
+        for bit_depth in [BitDepth::Int8, BitDepth::Int16, BitDepth::Int24, BitDepth::Float32] {
+            for channels in [Channels::Mono, Channels::Stereo, Channels::LeftOnly, Channels::RightOnly] {
+                let mut bytes = std::io::Cursor::new(Vec::new());
+                write_wav_to(&mut bytes, [0.5, -0.5, 2.0], 11025, &bit_depth, &channels).unwrap();
+                let bytes = bytes.into_inner();
+                assert_eq!(wav_spec(&bytes).unwrap(), (11025, channels.count() as usize, 3), "{bit_depth}, {channels}");
+
+                // Out of range samples are clipped rather than wrapped.
+                let mut frames = Vec::new();
+                for_each_wav_frame(&bytes, |frame| frames.push(frame.to_vec())).unwrap();
+                for (frame, sample) in frames.iter().zip([0.5, -0.5, 1.0]) {
+                    for (value, gain) in frame.iter().zip(channels.gains()) {
+                        assert!((value - sample * gain).abs() < 0.01, "{bit_depth}, {channels}: {frame:?}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn raw_samples_read_back() {
+        let samples = [0.0, 0.5, -0.5, 1.0, -1.0];
+        for format in [RawFormat::S16Le, RawFormat::F32Le] {
+            let mut bytes = Vec::new();
+            write_raw(&mut bytes, samples, &format, &Channels::Mono).unwrap();
+            let read = read_raw(bytes.as_slice(), &format).unwrap();
+            assert_eq!(read.len(), samples.len(), "{format}");
+            assert!(read.iter().zip(samples).all(|(read, sample)| (read - sample).abs() < 1e-4), "{format}: {read:?}");
+
+            // A sample cut short at the end of the stream is dropped.
+            bytes.pop();
+            assert_eq!(read_raw(bytes.as_slice(), &format).unwrap().len(), samples.len() - 1, "{format}");
+        }
+
+        // Stereo output interleaves the channels, with the silent one written as zero.
+        let mut bytes = Vec::new();
+        write_raw(&mut bytes, [0.5, 2.0], &RawFormat::F32Le, &Channels::RightOnly).unwrap();
+        assert_eq!(read_raw(bytes.as_slice(), &RawFormat::F32Le).unwrap(), vec![0.0, 0.5, 0.0, 1.0]);
+    }
+
+    // A mono 16 bit FLAC stream holding `samples` in a single frame, stored verbatim.
+    fn flac_bytes(sample_rate: u32, samples: &[i16]) -> Vec<u8> {
+        let crc = |bytes: &[u8], poly: u16, bits: u32| bytes.iter().fold(0u16, |mut crc, byte| {
+            crc ^= (*byte as u16) << (bits - 8);
+            for _ in 0..8 {
+                let top = crc & (1 << (bits - 1)) != 0;
+                crc = (crc << 1) & ((1u32 << bits) - 1) as u16;
+                if top {
+                    crc ^= poly;
+                }
+            }
+            crc
+        });
+
+        let mut bytes = b"fLaC".to_vec();
+        // The only metadata block, STREAMINFO, with unknown frame sizes and no MD5.
+        bytes.extend([0x80, 0, 0, 34]);
+        bytes.extend((samples.len() as u16).to_be_bytes());
+        bytes.extend((samples.len() as u16).to_be_bytes());
+        bytes.extend([0; 6]);
+        bytes.extend(((sample_rate as u64) << 44 | 15 << 36 | samples.len() as u64).to_be_bytes());
+        bytes.extend([0; 16]);
+
+        // Fixed block size frame 0, its length in the header, mono 16 bit at the stream's rate.
+        let mut frame = vec![0xFF, 0xF8, 0x60, 0x08, 0x00, (samples.len() - 1) as u8];
+        frame.push(crc(&frame, 0x07, 8) as u8);
+        frame.push(0x02);
+        frame.extend(samples.iter().flat_map(|sample| sample.to_be_bytes()));
+        frame.extend(crc(&frame, 0x8005, 16).to_be_bytes());
+        bytes.extend(frame);
+        bytes
+    }
+
+    #[test]
+    fn compressed_recordings_are_decoded() {
+        let samples: Vec<i16> = (0..64).map(|n| if n % 2 == 0 { 16384 } else { -8192 }).collect();
+        let directory = std::env::temp_dir();
+        let flac = directory.join(format!("sstv-{}.flac", std::process::id()));
+        let mp3 = directory.join(format!("sstv-{}.mp3", std::process::id()));
+        std::fs::write(&flac, flac_bytes(11025, &samples)).unwrap();
+        std::fs::write(&mp3, b"not really an mp3").unwrap();
+        let (decoded, damaged) = (read_audio(&flac, &InputChannel::Sum), read_audio(&mp3, &InputChannel::Sum));
+        std::fs::remove_file(&flac).unwrap();
+        std::fs::remove_file(&mp3).unwrap();
+
+        let (decoded, sample_rate) = decoded.unwrap();
+        assert_eq!(sample_rate, 11025);
+        assert_eq!(decoded, samples.iter().map(|&sample| sample as f32 / 32768.0).collect::<Vec<f32>>());
+        assert!(damaged.is_err());
+        assert!(audio_extensions().contains(&"flac"));
+    }
+
+    #[test]
+    fn channel_choice_picks_one_receiver() {
+        let data: Vec<u8> = [8192i16, -16384, 8192, -16384].iter().flat_map(|v| v.to_le_bytes()).collect();
+        let bytes = wav_bytes(WAVE_FORMAT_PCM, 2, 16, false, &data);
+
+        assert_eq!(parse_wav(&bytes, &InputChannel::Left).unwrap().0, vec![0.25, 0.25]);
+        assert_eq!(parse_wav(&bytes, &InputChannel::Right).unwrap().0, vec![-0.5, -0.5]);
+        assert_eq!(parse_wav(&bytes, &InputChannel::Sum).unwrap().0, vec![-0.125, -0.125]);
+    }
+}
@@ -0,0 +1,9 @@
+//! Headless `decode` and `encode` commands, built without the window.
+
+#[path = "../cli.rs"]
+mod cli;
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    std::process::exit(cli::run(&args));
+}
@@ -0,0 +1,421 @@
+//! The `decode` and `encode` commands, shared by the `sstv` binary and the window's headless mode.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Cursor, Read, Write};
+use std::path::Path;
+use std::time::Instant;
+use serde_json::{Value, json};
+use sstv::Mode;
+use sstv::audio::{self, BitDepth, Channels, InputChannel, RawFormat};
+use sstv::demod::{self, DemodMethod};
+use sstv::doppler::{self, Doppler, Observer, Satellite};
+use sstv::filter::PostFilter;
+use sstv::freq_to_img::{self, DecodeSettings};
+use sstv::img_to_freq::{self, EncodeSettings, Fit};
+use sstv::iq::{self, IqDemod, IqFormat, IqSettings};
+use sstv::overlay::{self, OverlayFields, OverlayTemplate};
+use sstv::save::{self, DecodeStats, ImageFormat, ImageInfo};
+
+const USAGE: &str = "\
+Usage:
+  sstv decode <recording> [-o <output image>] [options]
+  sstv encode <input image> -o <output.wav> [options]
+
+Recordings may be WAV, FLAC, Ogg Vorbis or MP3, or IQ from an SDR (.cu8, .cf32).
+Pass - as the decode input to read stdin, or as either command's output to write stdout.
+
+Decode options:
+  --mode <auto|raw|m1|m2|pd120|pd180>   default auto, read from the VIS code
+  --demod <phase|pll|zero>              default phase
+  --filter <none|average|median|lowpass> default lowpass
+  --no-flywheel                         turn off sync prediction
+  --no-afc                              turn off frequency correction
+  --format <wav|s16le|f32le>            default wav (any recording file), or s16le from stdin;
+                                        raw input is mono
+  --channel <sum|left|right>            channel of a multi-channel recording, default sum
+  --rate <Hz>                           sample rate of raw input, default 44100
+  --iq <cu8|cf32|wav>                   read an IQ recording, chosen by extension for .cu8/.cf32;
+                                        wav is a stereo SDR# recording
+  --iq-rate <Hz>                        sample rate of a raw IQ file, default 2048000
+  --offset <Hz>                         signal frequency relative to the centre, default 0
+  --iq-demod <fm|usb>                   default fm
+  --tle <file>                          correct IQ input for the Doppler shift of this satellite
+  --observer <file.json>                JSON with latitude, longitude (degrees) and altitude (m)
+  --downlink <Hz>                       transmitted frequency, default 145800000 (ISS)
+  --start <time>                        RFC 3339 start of the recording, default the file time
+                                        less its length
+
+Encode options:
+  --mode <m1|m2>                        default m1
+  --rate <Hz>                           default 44100
+  --depth <8|16|24|f32>                 default 16
+  --channels <mono|stereo|left|right>   default mono
+  --format <wav|s16le|f32le>            default wav, or s16le to stdout; --depth is WAV only
+  --level <dBFS>                        default -3
+  --fit <stretch|centre|letterbox>      default centre
+  --callsign <call>                     station being called, {callsign}
+  --mycall <call>                       our own callsign, {mycall}
+  --report <rsv>                        signal report, {report}
+  --grid <locator>                      grid square, {grid}
+  --template <file.json>                overlay template saved from the window
+
+Both commands print a JSON summary on success, to stderr when stdout carries audio.";
+
+// Options that take no value.
+const SWITCHES: [&str; 2] = ["--no-flywheel", "--no-afc"];
+
+struct Args {
+    input: String,
+    options: HashMap<String, String>,
+}
+
+impl Args {
+    fn parse(args: &[String]) -> Result<Args, String> {
+        let mut input = None;
+        let mut options = HashMap::new();
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            if SWITCHES.contains(&arg.as_str()) {
+                options.insert(arg.clone(), String::new());
+            } else if arg.starts_with('-') && arg != "-" {
+                let name = if arg == "-o" { "--output" } else { arg.as_str() };
+                let value = args.next().ok_or_else(|| format!("missing value for {arg}"))?;
+                options.insert(name.to_string(), value.clone());
+            } else if input.is_none() {
+                input = Some(arg.clone());
+            } else {
+                return Err(format!("unexpected argument {arg}"))
+            }
+        }
+
+        let input = input.ok_or("missing input file")?;
+        Ok(Args { input, options })
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        self.options.get(name).map(String::as_str)
+    }
+
+    fn has(&self, name: &str) -> bool {
+        self.options.contains_key(name)
+    }
+
+    fn number<T: std::str::FromStr>(&self, name: &str, default: T) -> Result<T, String> {
+        match self.get(name) {
+            Some(value) => value.parse().map_err(|_| format!("{name} expects a number, not {value}")),
+            None => Ok(default),
+        }
+    }
+
+    // A sample rate, which has to be above zero for any timing to be derived from it.
+    fn rate(&self, name: &str, default: u32) -> Result<u32, String> {
+        match self.number(name, default)? {
+            0 => Err(format!("{name} must be above 0 Hz")),
+            rate => Ok(rate),
+        }
+    }
+}
+
+fn parse_mode(name: &str) -> Result<Mode, String> {
+    match name.to_ascii_lowercase().as_str() {
+        "raw" => Ok(Mode::RAW),
+        "m1" => Ok(Mode::MartinM1),
+        "m2" => Ok(Mode::MartinM2),
+        "pd120" => Ok(Mode::PD120),
+        "pd180" => Ok(Mode::PD180),
+        _ => Err(format!("unknown mode {name}")),
+    }
+}
+
+// `None` for a file with a header, otherwise the raw sample format; raw is the default for a stream.
+fn parse_format(args: &Args, stream: bool) -> Result<Option<RawFormat>, String> {
+    match args.get("--format").unwrap_or(if stream { "s16le" } else { "wav" }) {
+        "wav" => Ok(None),
+        "s16le" => Ok(Some(RawFormat::S16Le)),
+        "f32le" => Ok(Some(RawFormat::F32Le)),
+        other => Err(format!("unknown audio format {other}")),
+    }
+}
+
+// `None` unless a TLE is given, in which case the observer is needed too.
+fn parse_doppler(args: &Args) -> Result<Option<Doppler>, String> {
+    let Some(tle) = args.get("--tle") else {
+        return Ok(None)
+    };
+    let satellite = Satellite::read_tle(Path::new(tle)).map_err(|e| format!("{tle}: {e}"))?;
+    let observer = args.get("--observer").ok_or("--tle needs --observer <file.json>")?;
+    let observer = Observer::load(Path::new(observer)).map_err(|e| format!("{observer}: {e}"))?;
+    let start = match args.get("--start") {
+        Some(start) => Some(chrono::DateTime::parse_from_rfc3339(start).map_err(|e| format!("--start {start}: {e}"))?.to_utc()),
+        None => None,
+    };
+    Ok(Some(Doppler { satellite, observer, downlink: args.number("--downlink", doppler::ISS_DOWNLINK)?, start }))
+}
+
+fn elapsed_ms(start: Instant) -> f64 {
+    start.elapsed().as_secs_f64() * 1000.0
+}
+
+/// Runs a command from the arguments after the program name and returns the exit code.
+pub fn run(args: &[String]) -> i32 {
+    let result = match args.first().map(String::as_str) {
+        Some("decode") => Args::parse(&args[1..]).and_then(|args| decode(&args)),
+        Some("encode") => Args::parse(&args[1..]).and_then(|args| encode(&args)),
+        Some("help" | "-h" | "--help") => {
+            println!("{USAGE}");
+            return 0
+        }
+        Some(command) => Err(format!("unknown command {command}\n\n{USAGE}")),
+        None => Err(USAGE.to_string()),
+    };
+
+    match result {
+        Ok(summary) if summary["output"] == "-" => {
+            eprintln!("{summary:#}");
+            0
+        }
+        Ok(summary) => {
+            println!("{summary:#}");
+            0
+        }
+        Err(e) => {
+            eprintln!("sstv: {e}");
+            1
+        }
+    }
+}
+
+fn decode(args: &Args) -> Result<Value, String> {
+    let total = Instant::now();
+
+    let demod_method = match args.get("--demod").unwrap_or("phase") {
+        "phase" => DemodMethod::PhaseDifference,
+        "pll" => DemodMethod::Pll,
+        "zero" => DemodMethod::ZeroCrossing,
+        other => return Err(format!("unknown demodulator {other}")),
+    };
+    let post_filter = match args.get("--filter").unwrap_or("lowpass") {
+        "none" => PostFilter::None,
+        "average" => PostFilter::MovingAverage,
+        "median" => PostFilter::Median,
+        "lowpass" => PostFilter::LowPass,
+        other => return Err(format!("unknown filter {other}")),
+    };
+    let settings = DecodeSettings {
+        post_filter,
+        flywheel: !args.has("--no-flywheel"),
+        afc: !args.has("--no-afc"),
+    };
+
+    let start = Instant::now();
+    let stdin = args.input == "-";
+    let input = Path::new(&args.input);
+    let format = parse_format(args, stdin)?;
+    let channel = match args.get("--channel").unwrap_or("sum") {
+        "sum" => InputChannel::Sum,
+        "left" => InputChannel::Left,
+        "right" => InputChannel::Right,
+        other => return Err(format!("unknown channel {other}")),
+    };
+    let iq_format = match args.get("--iq") {
+        Some("cu8") => Some(IqFormat::Cu8),
+        Some("cf32") => Some(IqFormat::Cf32),
+        Some("wav") => Some(IqFormat::Wav),
+        Some(other) => return Err(format!("unknown IQ format {other}")),
+        None => input.extension().and_then(|ext| IqFormat::from_extension(&ext.to_string_lossy())),
+    };
+    let doppler = parse_doppler(args)?;
+    if doppler.is_some() && iq_format.is_none() {
+        return Err(String::from("Doppler correction needs an IQ recording, pass --iq"))
+    }
+    let (samples, sample_rate) = match &format {
+        _ if iq_format.is_some() => {
+            if stdin {
+                return Err(String::from("IQ recordings cannot be read from stdin"))
+            }
+            let iq_settings = IqSettings {
+                format: iq_format.clone().unwrap(),
+                sample_rate: args.rate("--iq-rate", 2_048_000)?,
+                offset: args.number("--offset", 0.0)?,
+                demod: match args.get("--iq-demod").unwrap_or("fm") {
+                    "fm" => IqDemod::Nbfm,
+                    "usb" => IqDemod::Usb,
+                    other => return Err(format!("unknown IQ demodulator {other}")),
+                },
+                doppler: doppler.clone(),
+            };
+            iq::read_iq(input, &iq_settings)
+        }
+        None if stdin => {
+            let mut bytes = Vec::new();
+            std::io::stdin().read_to_end(&mut bytes).map_err(Into::into).and_then(|_| audio::parse_wav(&bytes, &channel))
+        }
+        None => audio::read_audio(input, &channel),
+        Some(format) => {
+            let samples = if stdin {
+                audio::read_raw(std::io::stdin().lock(), format)
+            } else {
+                File::open(input).map_err(Into::into).and_then(|file| audio::read_raw(file, format))
+            };
+            samples.map(|samples| (samples, 0))
+        }
+    }.map_err(|e| format!("{}: {e}", args.input))?;
+    let sample_rate = if format.is_some() && iq_format.is_none() { args.rate("--rate", 44100)? } else { sample_rate };
+    let read_ms = elapsed_ms(start);
+
+    let start = Instant::now();
+    let track = demod::frequency_track(&samples, sample_rate, demod_method.demodulator().as_ref(), &|_| {});
+    let demodulate_ms = elapsed_ms(start);
+
+    let start = Instant::now();
+    let requested = args.get("--mode").unwrap_or("auto");
+    let detected = requested.eq_ignore_ascii_case("auto");
+    let mode = if detected {
+        freq_to_img::detect_mode(&track.freqs, track.f_samp).map_err(|e| format!("{e}, pass --mode to choose one"))?
+    } else {
+        parse_mode(requested)?
+    };
+    let decoded = freq_to_img::decode_image(track.freqs, track.f_samp, mode.clone(), &settings).map_err(|e| e.to_string())?;
+    let decode_ms = elapsed_ms(start);
+
+    let mut info = ImageInfo::new(&mode, &decoded, (!stdin).then_some(input), track.snr_db);
+    if stdin {
+        info.received = Some(chrono::Utc::now());
+    }
+    let stats = DecodeStats::new(info, &decoded.image, &decoded.sync_confidence, &demod_method, &settings);
+
+    let start = Instant::now();
+    if let Some(output) = args.get("--output") {
+        let path = Path::new(output);
+        let format = path.extension().and_then(|ext| ImageFormat::from_extension(&ext.to_string_lossy())).unwrap_or(ImageFormat::Png);
+        if output == "-" {
+            // Some formats go back to fill in offsets, so the picture is put together in memory first.
+            let mut bytes = Cursor::new(Vec::new());
+            save::write_image(&mut bytes, &decoded.image, &format, &stats.info)
+                .and_then(|_| Ok(std::io::stdout().lock().write_all(bytes.get_ref())?))
+                .map_err(|e| format!("stdout: {e}"))?;
+        } else {
+            save::save_image(path, &decoded.image, &format, &stats.info).map_err(|e| format!("{output}: {e}"))?;
+        }
+    }
+    let write_ms = elapsed_ms(start);
+
+    let mut summary = serde_json::to_value(&stats).map_err(|e| e.to_string())?;
+    summary["input"] = json!(args.input);
+    summary["output"] = json!(args.get("--output"));
+    summary["format"] = match &iq_format {
+        Some(iq_format) => json!(format!("IQ {iq_format}")),
+        None => json!(format.map_or(String::from("wav"), |format| format.to_string())),
+    };
+    summary["doppler"] = json!(doppler.map(|doppler| doppler.satellite.name));
+    summary["mode_detected"] = json!(detected);
+    summary["duration_s"] = json!(samples.len() as f64 / sample_rate as f64);
+    summary["line_time_ms"] = json!(decoded.line_time.map(|t| t * 1000.0));
+    summary["timing_ms"] = json!({
+        "read": read_ms,
+        "demodulate": demodulate_ms,
+        "decode": decode_ms,
+        "write": write_ms,
+        "total": elapsed_ms(total),
+    });
+    Ok(summary)
+}
+
+fn encode(args: &Args) -> Result<Value, String> {
+    let total = Instant::now();
+    let output = args.get("--output").ok_or("missing -o <output.wav>")?;
+    let format = parse_format(args, output == "-")?;
+
+    let mode = parse_mode(args.get("--mode").unwrap_or("m1"))?;
+
+    let settings = EncodeSettings {
+        sample_rate: args.rate("--rate", 44100)?,
+        level_db: args.number("--level", -3.0)?,
+        bit_depth: match args.get("--depth").unwrap_or("16") {
+            "8" => BitDepth::Int8,
+            "16" => BitDepth::Int16,
+            "24" => BitDepth::Int24,
+            "f32" => BitDepth::Float32,
+            other => return Err(format!("unknown bit depth {other}")),
+        },
+        channels: match args.get("--channels").unwrap_or("mono") {
+            "mono" => Channels::Mono,
+            "stereo" => Channels::Stereo,
+            "left" => Channels::LeftOnly,
+            "right" => Channels::RightOnly,
+            other => return Err(format!("unknown channel layout {other}")),
+        },
+        fit: match args.get("--fit").unwrap_or("centre") {
+            "stretch" => Fit::Stretch,
+            "centre" | "center" => Fit::CentreCrop,
+            "letterbox" => Fit::Letterbox,
+            other => return Err(format!("unknown fit {other}")),
+        },
+        ..Default::default()
+    };
+
+    let start = Instant::now();
+    let mut image_data = img_to_freq::load_image(Path::new(&args.input)).map_err(|e| format!("{}: {e}", args.input))?;
+    let load_ms = elapsed_ms(start);
+
+    // Any text option turns the overlay on, with the built-in layout unless a template is given.
+    let fields = OverlayFields {
+        callsign: args.get("--callsign").unwrap_or_default().to_string(),
+        mycall: args.get("--mycall").unwrap_or_default().to_string(),
+        report: args.get("--report").unwrap_or_default().to_string(),
+        grid: args.get("--grid").unwrap_or_default().to_string(),
+    };
+    let use_overlay = ["--template", "--callsign", "--mycall", "--report", "--grid"].iter().any(|name| args.has(name));
+    let mut encode_settings = settings.clone();
+    if use_overlay {
+        let template = match args.get("--template") {
+            Some(path) => OverlayTemplate::load(Path::new(path)).map_err(|e| format!("{path}: {e}"))?,
+            None => OverlayTemplate::default(),
+        };
+        image_data = overlay::compose(&image_data, &mode, &settings, &template, &fields).map_err(|e| e.to_string())?;
+        // Already at the mode's resolution, so fitting again must leave it untouched.
+        encode_settings.fit = Fit::Stretch;
+    }
+
+    // The audio is written as it is generated, so encoding and writing are timed together.
+    let start = Instant::now();
+    let mut samples = 0;
+    let encoder = img_to_freq::Encoder::new(&image_data, mode.clone(), &encode_settings).map_err(|e| e.to_string())?;
+    let lines = encoder.lines();
+    let encoder = encoder.inspect(|_| samples += 1);
+    match &format {
+        // The WAV header is finished after the samples, so the file is put together in memory first.
+        None if output == "-" => {
+            let mut bytes = Cursor::new(Vec::new());
+            audio::write_wav_to(&mut bytes, encoder, settings.sample_rate, &settings.bit_depth, &settings.channels)
+                .and_then(|_| Ok(std::io::stdout().lock().write_all(bytes.get_ref())?))
+        }
+        None => audio::write_wav(Path::new(output), encoder, settings.sample_rate, &settings.bit_depth, &settings.channels),
+        Some(format) if output == "-" => audio::write_raw(std::io::stdout().lock(), encoder, format, &settings.channels),
+        // Opening for writing also works for a named pipe, blocking until something reads it.
+        Some(format) => File::create(output).map_err(Into::into).and_then(|file| audio::write_raw(file, encoder, format, &settings.channels)),
+    }.map_err(|e| format!("{output}: {e}"))?;
+    let encode_ms = elapsed_ms(start);
+
+    let width = mode.resolution().0;
+    Ok(json!({
+        "input": args.input,
+        "output": output,
+        "format": format.map_or(String::from("wav"), |format| format.to_string()),
+        "mode": mode.to_string(),
+        "vis": mode.vis(),
+        "width": width,
+        "lines": lines,
+        "overlay": use_overlay,
+        "sample_rate": settings.sample_rate,
+        "samples": samples,
+        "duration_s": samples as f64 / settings.sample_rate as f64,
+        "timing_ms": {
+            "load": load_ms,
+            "encode": encode_ms,
+            "total": elapsed_ms(total),
+        },
+    }))
+}
@@ -0,0 +1,229 @@
+use num_complex::{Complex, c32};
+use crate::{fft, resample};
+
+const TAU: f32 = 2.0 * std::f32::consts::PI;
+
+/// Turns the analytic signal from `fft::hilbert` into an instantaneous frequency track in Hz.
+pub trait Demodulator {
+    fn demodulate(&self, iq_samples: &[Complex<f32>], f_samp: f32) -> Vec<f32>;
+}
+
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub enum DemodMethod {
+    PhaseDifference,
+    Pll,
+    ZeroCrossing,
+}
+
+impl std::fmt::Display for DemodMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let label = match self {
+            DemodMethod::PhaseDifference => "Phase Difference",
+            DemodMethod::Pll => "PLL",
+            DemodMethod::ZeroCrossing => "Zero Crossing",
+        };
+        write!(f, "{label}")
+    }
+}
+
+impl DemodMethod {
+    pub fn demodulator(&self) -> Box<dyn Demodulator + Send> {
+        match self {
+            DemodMethod::PhaseDifference => Box::new(PhaseDifference),
+            DemodMethod::Pll => Box::new(Pll::new(3000.0)),
+            DemodMethod::ZeroCrossing => Box::new(ZeroCrossing),
+        }
+    }
+}
+
+/// Quadrature detector: the phase step between neighbouring samples, `arg(z[i] * conj(z[i-1]))`.
+pub struct PhaseDifference;
+
+impl Demodulator for PhaseDifference {
+    fn demodulate(&self, iq_samples: &[Complex<f32>], f_samp: f32) -> Vec<f32> {
+        let mut freqs: Vec<f32> = Vec::with_capacity(iq_samples.len().saturating_sub(1));
+
+        for i in 1..iq_samples.len() {
+            let diff = Complex::arg(iq_samples[i] * Complex::conj(&iq_samples[i-1]));
+            freqs.push(f32::abs(diff*f_samp)/TAU);
+        }
+
+        freqs
+    }
+}
+
+/// Frequency track of a recording and its estimated SNR, at `resample::WORKING_RATE`.
+pub struct Track {
+    pub freqs: Vec<f32>,
+    pub f_samp: f32,
+    pub snr_db: Option<f32>,
+}
+
+/// Runs a recording through resampling, the Hilbert transform and `demodulator`,
+/// reporting each stage through `status`.
+pub fn frequency_track(samples: &[f32], sample_rate: u32, demodulator: &dyn Demodulator, status: &dyn Fn(&str)) -> Track {
+    status("Resampling...");
+
+    let samples: Vec<Complex<f32>> = resample::resample(samples, sample_rate, resample::WORKING_RATE)
+        .into_iter()
+        .map(|z| c32(z, 0.0))
+        .collect();
+    let f_samp = resample::WORKING_RATE as f32;
+
+    status("Measuring SNR...");
+
+    let snr_db = fft::snr(samples.clone(), f_samp);
+
+    status("Performing Hilbert Transform...");
+
+    let iq_samples = fft::hilbert(samples, f_samp, 900.0, 2500.0);
+
+    status("Calculating Phase...");
+
+    let freqs = demodulator.demodulate(&iq_samples, f_samp);
+
+    Track { freqs, f_samp, snr_db }
+}
+
+/// Second-order phase locked loop with a damping factor of 1/√2, for a fast settle with little overshoot,
+/// and the given loop noise bandwidth in Hz.
+pub struct Pll {
+    pub bandwidth: f32,
+}
+
+impl Pll {
+    pub fn new(bandwidth: f32) -> Self {
+        Self { bandwidth }
+    }
+}
+
+impl Demodulator for Pll {
+    fn demodulate(&self, iq_samples: &[Complex<f32>], f_samp: f32) -> Vec<f32> {
+        let zeta: f32 = std::f32::consts::FRAC_1_SQRT_2;
+        let theta = (self.bandwidth / f_samp) / (zeta + 1.0/(4.0*zeta));
+        let d = 1.0 + 2.0*zeta*theta + theta*theta;
+        let kp = 4.0*zeta*theta / d;
+        let ki = 4.0*theta*theta / d;
+
+        // Start in the middle of the SSTV band so the loop pulls in quickly.
+        let mut omega = TAU * 1900.0 / f_samp;
+        let mut phase: f32 = 0.0;
+        let mut freqs: Vec<f32> = Vec::with_capacity(iq_samples.len());
+
+        for z in iq_samples {
+            let err = Complex::arg(z * Complex::from_polar(1.0, -phase));
+            omega += ki * err;
+            phase += omega + kp * err;
+            if phase >= TAU {
+                phase -= TAU;
+            } else if phase < 0.0 {
+                phase += TAU;
+            }
+            freqs.push(f32::abs(omega * f_samp) / TAU);
+        }
+
+        freqs
+    }
+}
+
+/// Counts zero crossings of the real signal and reports the period spanned by the last two.
+pub struct ZeroCrossing;
+
+impl Demodulator for ZeroCrossing {
+    fn demodulate(&self, iq_samples: &[Complex<f32>], f_samp: f32) -> Vec<f32> {
+        let mut freqs: Vec<f32> = Vec::with_capacity(iq_samples.len());
+        let mut crossings: [f32; 3] = [0.0; 3];
+        let mut found: usize = 0;
+        let mut f: f32 = 0.0;
+
+        for i in 0..iq_samples.len() {
+            if i > 0 {
+                let a = iq_samples[i-1].re;
+                let b = iq_samples[i].re;
+                if (a < 0.0) != (b < 0.0) && a != b {
+                    // Interpolate the crossing between the two samples.
+                    let t = (i - 1) as f32 + a / (a - b);
+                    crossings = [crossings[1], crossings[2], t];
+                    found += 1;
+                    if found >= 3 {
+                        f = f_samp / (crossings[2] - crossings[0]);
+                    }
+                }
+            }
+            freqs.push(f);
+        }
+
+        freqs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Deterministic gaussian noise so the comparison is repeatable.
+    fn noise(seed: &mut u64) -> f32 {
+        let mut uniform = || {
+            *seed ^= *seed << 13;
+            *seed ^= *seed >> 7;
+            *seed ^= *seed << 17;
+            ((*seed >> 11) as f32 + 1.0) / ((1u64 << 53) as f32)
+        };
+        let (u1, u2) = (uniform(), uniform());
+        f32::sqrt(-2.0 * u1.ln()) * f32::cos(TAU * u2)
+    }
+
+    // Steps between 1500 and 2300 Hz every 5 ms; returns the signal and its true frequency.
+    fn test_signal(f_samp: f32, snr_db: f32) -> (Vec<Complex<f32>>, Vec<f32>) {
+        let noise_amp = f32::sqrt(0.5 / 10f32.powf(snr_db / 10.0));
+        let mut seed: u64 = 0x5eed;
+        let mut phase: f32 = 0.0;
+        let mut samples = Vec::new();
+        let mut truth = Vec::new();
+
+        for i in 0..(f_samp * 0.5) as usize {
+            let f = if ((i as f32 / (f_samp * 0.005)) as usize).is_multiple_of(2) { 1500.0 } else { 2300.0 };
+            phase = (phase + TAU * f / f_samp) % TAU;
+            samples.push(c32(phase.sin() + noise_amp * noise(&mut seed), 0.0));
+            truth.push(f);
+        }
+
+        (samples, truth)
+    }
+
+    // RMS error away from the tone steps, where every detector needs time to settle.
+    fn rms_error(freqs: &[f32], truth: &[f32], f_samp: f32) -> f32 {
+        let settle = (f_samp * 0.001) as usize;
+        let step = (f_samp * 0.005) as usize;
+        let mut sum = 0.0;
+        let mut count = 0;
+        for (i, (f, t)) in freqs.iter().zip(truth).enumerate() {
+            if i > step && i % step > settle {
+                sum += (f - t) * (f - t);
+                count += 1;
+            }
+        }
+        f32::sqrt(sum / count as f32)
+    }
+
+    #[test]
+    fn demodulators_track_noisy_signal() {
+        let f_samp = 44100.0;
+        // Largest RMS error allowed at 30 dB and at 10 dB SNR, about a third above what each detector manages.
+        let bounds = [
+            (DemodMethod::PhaseDifference, 70.0, 85.0),
+            (DemodMethod::Pll, 100.0, 105.0),
+            (DemodMethod::ZeroCrossing, 145.0, 150.0),
+        ];
+        for (method, clean_bound, noisy_bound) in bounds {
+            for (snr_db, bound) in [(30.0, clean_bound), (10.0, noisy_bound)] {
+                let (samples, truth) = test_signal(f_samp, snr_db);
+                let iq = fft::hilbert(samples, f_samp, 900.0, 2500.0);
+                let freqs = method.demodulator().demodulate(&iq, f_samp);
+                let error = rms_error(&freqs, &truth, f_samp);
+                assert!(error < bound, "{method} at {snr_db} dB SNR: {error} Hz RMS");
+            }
+        }
+    }
+}
@@ -0,0 +1,503 @@
+use std::f64::consts::{PI, TAU};
+use std::path::Path;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use crate::{Error, Result};
+
+/// Downlink of the ISS SSTV events.
+pub const ISS_DOWNLINK: f64 = 145_800_000.0;
+
+// WGS72, which the published element sets are fitted with.
+const EARTH_RADIUS_KM: f64 = 6378.135;
+const XKE: f64 = 0.07436691613317342;
+const J2: f64 = 0.001082616;
+const J3: f64 = -0.00000253881;
+const J4: f64 = -0.00000165597;
+
+// WGS84, for the observer.
+const WGS84_RADIUS_KM: f64 = 6378.137;
+const WGS84_FLATTENING: f64 = 1.0 / 298.257223563;
+
+const EARTH_ROTATION: f64 = 7.292115e-5;
+const LIGHT_KM_S: f64 = 299_792.458;
+
+/// An orbit from a two line element set, prepared for the SGP4 model. Only near earth
+/// orbits, with periods under 225 minutes, are handled, which covers the ISS and the
+/// amateur satellites in low orbit.
+#[derive(Clone)]
+pub struct Satellite {
+    pub name: String,
+    pub epoch: DateTime<Utc>,
+    ecco: f64,
+    argpo: f64,
+    inclo: f64,
+    mo: f64,
+    nodeo: f64,
+    no: f64,
+    bstar: f64,
+    // Terms fixed at the epoch.
+    isimp: bool,
+    con41: f64,
+    x1mth2: f64,
+    x7thm1: f64,
+    eta: f64,
+    cc1: f64,
+    cc4: f64,
+    cc5: f64,
+    d2: f64,
+    d3: f64,
+    d4: f64,
+    t2cof: f64,
+    t3cof: f64,
+    t4cof: f64,
+    t5cof: f64,
+    mdot: f64,
+    argpdot: f64,
+    nodedot: f64,
+    nodecf: f64,
+    omgcof: f64,
+    xmcof: f64,
+    xlcof: f64,
+    aycof: f64,
+    delmo: f64,
+    sinmao: f64,
+}
+
+// Columns `start..end` of a TLE line, counted from 1 as in the format description.
+fn field(line: &str, start: usize, end: usize) -> Result<&str> {
+    line.get(start - 1..end).map(str::trim).ok_or_else(|| Error::Orbit(format!("TLE line is too short: {line}")))
+}
+
+fn number(line: &str, start: usize, end: usize) -> Result<f64> {
+    let text = field(line, start, end)?;
+    text.parse().map_err(|_| Error::Orbit(format!("cannot read {text:?} in TLE line: {line}")))
+}
+
+// Decimal point assumed before the mantissa, e.g. " 12345-3" is 0.12345e-3.
+fn implied_decimal(line: &str, start: usize, end: usize) -> Result<f64> {
+    let text = field(line, start, end)?;
+    let (mantissa, exponent) = match text.rfind(['-', '+']) {
+        Some(at) if at > 0 => text.split_at(at),
+        _ => (text, "0"),
+    };
+    let (sign, digits) = match mantissa.strip_prefix('-') {
+        Some(digits) => (-1.0, digits),
+        None => (1.0, mantissa.trim_start_matches('+')),
+    };
+    let value = format!("0.{digits}e{exponent}").parse::<f64>().map_err(|_| Error::Orbit(format!("cannot read {text:?} in TLE line: {line}")))?;
+    Ok(sign * value)
+}
+
+// Last column of each line is the sum of its digits, with '-' counting as one, modulo 10.
+fn checksum_matches(line: &str) -> bool {
+    let Some(expected) = line.chars().nth(68).and_then(|c| c.to_digit(10)) else {
+        return false
+    };
+    let sum: u32 = line.chars().take(68).map(|c| match c {
+        '-' => 1,
+        c => c.to_digit(10).unwrap_or(0),
+    }).sum();
+    sum % 10 == expected
+}
+
+impl Satellite {
+    /// Reads an element set in the usual two or three line form; the name line is optional.
+    pub fn from_tle(text: &str) -> Result<Self> {
+        let lines: Vec<&str> = text.lines().map(str::trim_end).filter(|line| !line.trim().is_empty()).collect();
+        let first = lines.iter().position(|line| line.starts_with("1 ")).ok_or_else(|| Error::Orbit(String::from("no TLE line 1 found")))?;
+        let (line1, line2) = match (lines.get(first), lines.get(first + 1)) {
+            (Some(line1), Some(line2)) if line2.starts_with("2 ") => (*line1, *line2),
+            _ => return Err(Error::Orbit(String::from("TLE line 2 must follow line 1"))),
+        };
+        for line in [line1, line2] {
+            if !checksum_matches(line) {
+                return Err(Error::Orbit(format!("TLE checksum does not match: {line}")))
+            }
+        }
+        let name = match first {
+            0 => field(line1, 3, 7)?.to_string(),
+            _ => lines[first - 1].trim_start_matches("0 ").trim().to_string(),
+        };
+
+        let year = number(line1, 19, 20)? as i32;
+        let year = if year < 57 { 2000 + year } else { 1900 + year };
+        let day = number(line1, 21, 32)?;
+        let new_year = NaiveDate::from_ymd_opt(year, 1, 1).unwrap().and_hms_opt(0, 0, 0).unwrap().and_utc();
+        let epoch = new_year + Duration::microseconds(((day - 1.0) * 86_400e6).round() as i64);
+
+        let ecco = format!("0.{}", field(line2, 27, 33)?).parse::<f64>().map_err(|_| Error::Orbit(format!("cannot read the eccentricity in TLE line: {line2}")))?;
+        Self::new(
+            name,
+            epoch,
+            ecco,
+            number(line2, 35, 42)?.to_radians(),
+            number(line2, 9, 16)?.to_radians(),
+            number(line2, 44, 51)?.to_radians(),
+            number(line2, 18, 25)?.to_radians(),
+            number(line2, 53, 63)? * TAU / 1440.0,
+            implied_decimal(line1, 54, 61)?,
+        )
+    }
+
+    pub fn read_tle(path: &Path) -> Result<Self> {
+        Self::from_tle(&std::fs::read_to_string(path)?)
+    }
+
+    // The SGP4 initialisation, after Vallado et al., "Revisiting Spacetrack Report #3" (2006).
+    #[allow(clippy::too_many_arguments)]
+    fn new(name: String, epoch: DateTime<Utc>, ecco: f64, argpo: f64, inclo: f64, mo: f64, nodeo: f64, no_kozai: f64, bstar: f64) -> Result<Self> {
+        if !(0.0..1.0).contains(&ecco) || no_kozai <= 0.0 {
+            return Err(Error::Orbit(String::from("the elements do not describe an orbit")))
+        }
+        let x2o3 = 2.0 / 3.0;
+        let j3oj2 = J3 / J2;
+
+        let eccsq = ecco * ecco;
+        let omeosq = 1.0 - eccsq;
+        let rteosq = omeosq.sqrt();
+        let cosio = inclo.cos();
+        let cosio2 = cosio * cosio;
+
+        // Recover the mean motion the elements were fitted with.
+        let ak = (XKE / no_kozai).powf(x2o3);
+        let d1 = 0.75 * J2 * (3.0 * cosio2 - 1.0) / (rteosq * omeosq);
+        let del = d1 / (ak * ak);
+        let adel = ak * (1.0 - del * del - del * (1.0 / 3.0 + 134.0 * del * del / 81.0));
+        let del = d1 / (adel * adel);
+        let no = no_kozai / (1.0 + del);
+        if TAU / no >= 225.0 {
+            return Err(Error::Orbit(String::from("deep space orbits are not supported")))
+        }
+
+        let ao = (XKE / no).powf(x2o3);
+        let sinio = inclo.sin();
+        let po = ao * omeosq;
+        let con42 = 1.0 - 5.0 * cosio2;
+        let con41 = -con42 - cosio2 - cosio2;
+        let posq = po * po;
+        let rp = ao * (1.0 - ecco);
+
+        // Atmospheric density parameters, lowered for low perigees.
+        let ss = 78.0 / EARTH_RADIUS_KM + 1.0;
+        let qzms2t = ((120.0 - 78.0) / EARTH_RADIUS_KM).powi(4);
+        let isimp = rp < 220.0 / EARTH_RADIUS_KM + 1.0;
+        let perigee = (rp - 1.0) * EARTH_RADIUS_KM;
+        let (sfour, qzms24) = if perigee < 156.0 {
+            let sfour = if perigee < 98.0 { 20.0 } else { perigee - 78.0 };
+            (sfour / EARTH_RADIUS_KM + 1.0, ((120.0 - sfour) / EARTH_RADIUS_KM).powi(4))
+        } else {
+            (ss, qzms2t)
+        };
+
+        let pinvsq = 1.0 / posq;
+        let tsi = 1.0 / (ao - sfour);
+        let eta = ao * ecco * tsi;
+        let etasq = eta * eta;
+        let eeta = ecco * eta;
+        let psisq = (1.0 - etasq).abs();
+        let coef = qzms24 * tsi.powi(4);
+        let coef1 = coef / psisq.powf(3.5);
+        let cc2 = coef1 * no * (ao * (1.0 + 1.5 * etasq + eeta * (4.0 + etasq))
+            + 0.375 * J2 * tsi / psisq * con41 * (8.0 + 3.0 * etasq * (8.0 + etasq)));
+        let cc1 = bstar * cc2;
+        let cc3 = if ecco > 1.0e-4 { -2.0 * coef * tsi * j3oj2 * no * sinio / ecco } else { 0.0 };
+        let x1mth2 = 1.0 - cosio2;
+        let cc4 = 2.0 * no * coef1 * ao * omeosq * (eta * (2.0 + 0.5 * etasq) + ecco * (0.5 + 2.0 * etasq)
+            - J2 * tsi / (ao * psisq) * (-3.0 * con41 * (1.0 - 2.0 * eeta + etasq * (1.5 - 0.5 * eeta))
+            + 0.75 * x1mth2 * (2.0 * etasq - eeta * (1.0 + etasq)) * (2.0 * argpo).cos()));
+        let cc5 = 2.0 * coef1 * ao * omeosq * (1.0 + 2.75 * (etasq + eeta) + eeta * etasq);
+
+        // Secular rates from the earth's oblateness.
+        let cosio4 = cosio2 * cosio2;
+        let temp1 = 1.5 * J2 * pinvsq * no;
+        let temp2 = 0.5 * temp1 * J2 * pinvsq;
+        let temp3 = -0.46875 * J4 * pinvsq * pinvsq * no;
+        let mdot = no + 0.5 * temp1 * rteosq * con41 + 0.0625 * temp2 * rteosq * (13.0 - 78.0 * cosio2 + 137.0 * cosio4);
+        let argpdot = -0.5 * temp1 * con42 + 0.0625 * temp2 * (7.0 - 114.0 * cosio2 + 395.0 * cosio4)
+            + temp3 * (3.0 - 36.0 * cosio2 + 49.0 * cosio4);
+        let xhdot1 = -temp1 * cosio;
+        let nodedot = xhdot1 + (0.5 * temp2 * (4.0 - 19.0 * cosio2) + 2.0 * temp3 * (3.0 - 7.0 * cosio2)) * cosio;
+
+        let omgcof = bstar * cc3 * argpo.cos();
+        let xmcof = if ecco > 1.0e-4 { -x2o3 * coef * bstar / eeta } else { 0.0 };
+        let nodecf = 3.5 * omeosq * xhdot1 * cc1;
+        let t2cof = 1.5 * cc1;
+        let xlcof = -0.25 * j3oj2 * sinio * (3.0 + 5.0 * cosio) / f64::max((1.0 + cosio).abs(), 1.5e-12);
+        let aycof = -0.5 * j3oj2 * sinio;
+        let delmo = (1.0 + eta * mo.cos()).powi(3);
+
+        let (d2, d3, d4, t3cof, t4cof, t5cof) = if isimp {
+            (0.0, 0.0, 0.0, 0.0, 0.0, 0.0)
+        } else {
+            let cc1sq = cc1 * cc1;
+            let d2 = 4.0 * ao * tsi * cc1sq;
+            let temp = d2 * tsi * cc1 / 3.0;
+            let d3 = (17.0 * ao + sfour) * temp;
+            let d4 = 0.5 * temp * ao * tsi * (221.0 * ao + 31.0 * sfour) * cc1;
+            let t3cof = d2 + 2.0 * cc1sq;
+            let t4cof = 0.25 * (3.0 * d3 + cc1 * (12.0 * d2 + 10.0 * cc1sq));
+            let t5cof = 0.2 * (3.0 * d4 + 12.0 * cc1 * d3 + 6.0 * d2 * d2 + 15.0 * cc1sq * (2.0 * d2 + cc1sq));
+            (d2, d3, d4, t3cof, t4cof, t5cof)
+        };
+
+        Ok(Self {
+            name, epoch, ecco, argpo, inclo, mo, nodeo, no, bstar,
+            isimp, con41, x1mth2, x7thm1: 7.0 * cosio2 - 1.0, eta, cc1, cc4, cc5, d2, d3, d4,
+            t2cof, t3cof, t4cof, t5cof, mdot, argpdot, nodedot, nodecf, omgcof, xmcof, xlcof, aycof,
+            delmo, sinmao: mo.sin(),
+        })
+    }
+
+    /// Position in km and velocity in km/s in the TEME frame, `minutes` after the epoch.
+    pub fn propagate(&self, minutes: f64) -> Result<([f64; 3], [f64; 3])> {
+        let t = minutes;
+        let t2 = t * t;
+        let xmdf = self.mo + self.mdot * t;
+        let argpdf = self.argpo + self.argpdot * t;
+        let nodedf = self.nodeo + self.nodedot * t;
+        let mut argpm = argpdf;
+        let mut mm = xmdf;
+        let mut nodem = nodedf + self.nodecf * t2;
+        let mut tempa = 1.0 - self.cc1 * t;
+        let mut tempe = self.bstar * self.cc4 * t;
+        let mut templ = self.t2cof * t2;
+
+        if !self.isimp {
+            let delomg = self.omgcof * t;
+            let delm = self.xmcof * ((1.0 + self.eta * xmdf.cos()).powi(3) - self.delmo);
+            mm = xmdf + delomg + delm;
+            argpm = argpdf - delomg - delm;
+            let t3 = t2 * t;
+            let t4 = t3 * t;
+            tempa -= self.d2 * t2 + self.d3 * t3 + self.d4 * t4;
+            tempe += self.bstar * self.cc5 * (mm.sin() - self.sinmao);
+            templ += self.t3cof * t3 + t4 * (self.t4cof + t * self.t5cof);
+        }
+
+        let am = (XKE / self.no).powf(2.0 / 3.0) * tempa * tempa;
+        let nm = XKE / am.powf(1.5);
+        let em = self.ecco - tempe;
+        if !(-0.001..1.0).contains(&em) || am < 0.95 {
+            return Err(Error::Orbit(format!("the orbit has decayed {minutes:.0} minutes after the epoch")))
+        }
+        let em = em.max(1.0e-6);
+        mm += self.no * templ;
+        let xlm = (mm + argpm + nodem).rem_euclid(TAU);
+        nodem = nodem.rem_euclid(TAU);
+        argpm = argpm.rem_euclid(TAU);
+        mm = (xlm - argpm - nodem).rem_euclid(TAU);
+
+        // Long period periodics.
+        let sinim = self.inclo.sin();
+        let cosim = self.inclo.cos();
+        let axnl = em * argpm.cos();
+        let temp = 1.0 / (am * (1.0 - em * em));
+        let aynl = em * argpm.sin() + temp * self.aycof;
+        let xl = mm + argpm + nodem + temp * self.xlcof * axnl;
+
+        // Kepler's equation.
+        let u = (xl - nodem).rem_euclid(TAU);
+        let mut eo1 = u;
+        let (mut sineo1, mut coseo1) = eo1.sin_cos();
+        for _ in 0..10 {
+            (sineo1, coseo1) = eo1.sin_cos();
+            let step = (u - aynl * coseo1 + axnl * sineo1 - eo1) / (1.0 - coseo1 * axnl - sineo1 * aynl);
+            eo1 += step.clamp(-0.95, 0.95);
+            if step.abs() < 1.0e-12 {
+                break
+            }
+        }
+
+        // Short period periodics.
+        let ecose = axnl * coseo1 + aynl * sineo1;
+        let esine = axnl * sineo1 - aynl * coseo1;
+        let el2 = axnl * axnl + aynl * aynl;
+        let pl = am * (1.0 - el2);
+        if pl < 0.0 {
+            return Err(Error::Orbit(format!("the orbit has decayed {minutes:.0} minutes after the epoch")))
+        }
+        let rl = am * (1.0 - ecose);
+        let rdotl = am.sqrt() * esine / rl;
+        let rvdotl = pl.sqrt() / rl;
+        let betal = (1.0 - el2).sqrt();
+        let temp = esine / (1.0 + betal);
+        let sinu = am / rl * (sineo1 - aynl - axnl * temp);
+        let cosu = am / rl * (coseo1 - axnl + aynl * temp);
+        let su = sinu.atan2(cosu);
+        let sin2u = (cosu + cosu) * sinu;
+        let cos2u = 1.0 - 2.0 * sinu * sinu;
+        let temp = 1.0 / pl;
+        let temp1 = 0.5 * J2 * temp;
+        let temp2 = temp1 * temp;
+
+        let mrt = rl * (1.0 - 1.5 * temp2 * betal * self.con41) + 0.5 * temp1 * self.x1mth2 * cos2u;
+        let su = su - 0.25 * temp2 * self.x7thm1 * sin2u;
+        let xnode = nodem + 1.5 * temp2 * cosim * sin2u;
+        let xinc = self.inclo + 1.5 * temp2 * cosim * sinim * cos2u;
+        let mvt = rdotl - nm * temp1 * self.x1mth2 * sin2u / XKE;
+        let rvdot = rvdotl + nm * temp1 * (self.x1mth2 * cos2u + 1.5 * self.con41) / XKE;
+
+        let (sinsu, cossu) = su.sin_cos();
+        let (snod, cnod) = xnode.sin_cos();
+        let (sini, cosi) = xinc.sin_cos();
+        let xmx = -snod * cosi;
+        let xmy = cnod * cosi;
+        let ux = [xmx * sinsu + cnod * cossu, xmy * sinsu + snod * cossu, sini * sinsu];
+        let vx = [xmx * cossu - cnod * sinsu, xmy * cossu - snod * sinsu, sini * cossu];
+
+        let km_s = EARTH_RADIUS_KM * XKE / 60.0;
+        let position = ux.map(|u| mrt * u * EARTH_RADIUS_KM);
+        let velocity = [0, 1, 2].map(|i| (mvt * ux[i] + rvdot * vx[i]) * km_s);
+        Ok((position, velocity))
+    }
+}
+
+/// Where the receiving station is, read from a JSON file such as
+/// `{ "latitude": 51.48, "longitude": -0.01, "altitude": 20 }`.
+#[derive(Clone)]
+#[derive(Serialize, Deserialize)]
+pub struct Observer {
+    /// Degrees north.
+    pub latitude: f64,
+    /// Degrees east.
+    pub longitude: f64,
+    /// Metres above the ellipsoid.
+    #[serde(default)]
+    pub altitude: f64,
+}
+
+impl Observer {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        serde_json::from_str(&text).map_err(|e| Error::Orbit(format!("cannot read the observer: {e}")))
+    }
+
+    // Earth fixed position in km.
+    fn position(&self) -> [f64; 3] {
+        let (sin_lat, cos_lat) = self.latitude.to_radians().sin_cos();
+        let (sin_lon, cos_lon) = self.longitude.to_radians().sin_cos();
+        let e2 = WGS84_FLATTENING * (2.0 - WGS84_FLATTENING);
+        let n = WGS84_RADIUS_KM / (1.0 - e2 * sin_lat * sin_lat).sqrt();
+        let height = self.altitude / 1000.0;
+        [(n + height) * cos_lat * cos_lon, (n + height) * cos_lat * sin_lon, (n * (1.0 - e2) + height) * sin_lat]
+    }
+}
+
+// Greenwich mean sidereal time in radians, IAU 1982, taking UT1 as UTC.
+fn sidereal_time(time: DateTime<Utc>) -> f64 {
+    let julian = time.timestamp_micros() as f64 / 86400e6 + 2440587.5;
+    let centuries = (julian - 2451545.0) / 36525.0;
+    let seconds = -6.2e-6 * centuries.powi(3) + 0.093104 * centuries.powi(2)
+        + (876600.0 * 3600.0 + 8640184.812866) * centuries + 67310.54841;
+    (seconds * PI / 43200.0).rem_euclid(TAU)
+}
+
+/// Predicts how far a satellite's downlink is shifted at a receiving station.
+#[derive(Clone)]
+pub struct Doppler {
+    pub satellite: Satellite,
+    pub observer: Observer,
+    /// Transmitted frequency in Hz.
+    pub downlink: f64,
+    /// When the recording began; taken from the file when not given.
+    pub start: Option<DateTime<Utc>>,
+}
+
+impl Doppler {
+    /// Received minus transmitted frequency in Hz at `time`; positive while the satellite approaches.
+    pub fn shift(&self, time: DateTime<Utc>) -> Result<f64> {
+        let minutes = (time - self.satellite.epoch).num_microseconds().unwrap_or(i64::MAX) as f64 / 60e6;
+        let (position, velocity) = self.satellite.propagate(minutes)?;
+
+        // Turn the TEME vectors into the earth fixed frame the observer sits still in.
+        let (sin_g, cos_g) = sidereal_time(time).sin_cos();
+        let rotate = |v: [f64; 3]| [cos_g * v[0] + sin_g * v[1], -sin_g * v[0] + cos_g * v[1], v[2]];
+        let position = rotate(position);
+        let velocity = rotate(velocity);
+        let velocity = [velocity[0] + EARTH_ROTATION * position[1], velocity[1] - EARTH_ROTATION * position[0], velocity[2]];
+
+        let observer = self.observer.position();
+        let range = [0, 1, 2].map(|i| position[i] - observer[i]);
+        let distance = range.iter().map(|r| r * r).sum::<f64>().sqrt();
+        let range_rate = (0..3).map(|i| range[i] * velocity[i]).sum::<f64>() / distance;
+        Ok(-self.downlink * range_rate / LIGHT_KM_S)
+    }
+
+    /// The shift every `step` seconds over `duration` seconds from `start`, endpoints included.
+    pub fn curve(&self, start: DateTime<Utc>, duration: f64, step: f64) -> Result<Vec<f64>> {
+        let points = (duration / step).ceil() as usize + 1;
+        (0..points)
+            .map(|n| self.shift(start + Duration::microseconds((n as f64 * step * 1e6) as i64)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test case 00005 from "Revisiting Spacetrack Report #3".
+    const VANGUARD: &str = "\
+1 00005U 58002B   00179.78495062  .00000023  00000-0  28098-4 0  4753
+2 00005  34.2682 348.7242 1859667 331.7664  19.3264 10.82419157413667";
+
+    const ISS: &str = "\
+ISS (ZARYA)
+1 25544U 98067A   08264.51782528 -.00002182  00000-0 -11606-4 0  2927
+2 25544  51.6416 247.4627 0006703 130.5360 325.0288 15.72125391563537";
+
+    #[test]
+    fn propagation_matches_the_reference() {
+        let satellite = Satellite::from_tle(VANGUARD).unwrap();
+        for (minutes, position, velocity) in [
+            (0.0, [7022.46529266, -1400.08296755, 0.03995155], [1.893841015, 6.405893759, 4.534807250]),
+            (360.0, [-7154.03120202, -3783.17682504, -3536.19412294], [4.741887409, -4.151817765, -2.093935425]),
+        ] {
+            let (r, v) = satellite.propagate(minutes).unwrap();
+            for i in 0..3 {
+                assert!((r[i] - position[i]).abs() < 1e-3, "{minutes} min: {r:?}");
+                assert!((v[i] - velocity[i]).abs() < 1e-6, "{minutes} min: {v:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn corrupted_lines_are_refused() {
+        assert!(Satellite::from_tle(&VANGUARD.replace("34.2682", "34.2683")).is_err());
+    }
+
+    #[test]
+    fn sidereal_time_matches_the_j2000_value() {
+        let noon = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap().and_hms_opt(12, 0, 0).unwrap().and_utc();
+        assert!((sidereal_time(noon).to_degrees() - 280.46061837).abs() < 1e-6);
+    }
+
+    #[test]
+    fn iss_overhead_pass_rises_then_falls() {
+        let satellite = Satellite::from_tle(ISS).unwrap();
+        let overhead = satellite.epoch + Duration::minutes(30);
+
+        // Stand under the satellite, found from its earth fixed position at that moment.
+        let minutes = (overhead - satellite.epoch).num_seconds() as f64 / 60.0;
+        let (position, _) = satellite.propagate(minutes).unwrap();
+        let (sin_g, cos_g) = sidereal_time(overhead).sin_cos();
+        let (x, y) = (cos_g * position[0] + sin_g * position[1], -sin_g * position[0] + cos_g * position[1]);
+        let observer = Observer {
+            latitude: f64::atan2(position[2], x.hypot(y)).to_degrees(),
+            longitude: f64::atan2(y, x).to_degrees(),
+            altitude: 0.0,
+        };
+        let doppler = Doppler { satellite, observer, downlink: ISS_DOWNLINK, start: None };
+
+        let approaching = doppler.shift(overhead - Duration::minutes(2)).unwrap();
+        let receding = doppler.shift(overhead + Duration::minutes(2)).unwrap();
+        assert!((2500.0..3600.0).contains(&approaching), "{approaching} Hz two minutes before");
+        assert!((-3600.0..-2500.0).contains(&receding), "{receding} Hz two minutes after");
+
+        // The shift passes through zero at closest approach, within a few seconds of overhead.
+        let curve = doppler.curve(overhead - Duration::seconds(60), 120.0, 1.0).unwrap();
+        let crossing = curve.windows(2).position(|pair| pair[0] >= 0.0 && pair[1] < 0.0).unwrap();
+        assert!((crossing as i64 - 60).abs() <= 5, "zero crossing {crossing} s into the curve");
+        assert!(curve.iter().all(|shift| shift.abs() < 3600.0));
+    }
+}
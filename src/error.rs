@@ -0,0 +1,130 @@
+use std::fmt;
+
+/// Everything that can go wrong reading, decoding or encoding a transmission.
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    /// The file is a WAV the reader cannot handle, or not a WAV at all.
+    UnsupportedWav(String),
+    /// A FLAC, Ogg or MP3 file that could not be decoded.
+    UnsupportedAudio(String),
+    /// A picture could not be read or written.
+    Image(image::ImageError),
+    /// No leader tone or sync pulses were found in the recording.
+    NoSignal,
+    /// The leader was found but no VIS code could be read after it.
+    NoVis,
+    /// A VIS code was read, but its parity bit did not match; holds the seven data bits.
+    VisParity(u8),
+    /// A VIS code was read correctly but does not belong to a mode the decoder knows.
+    UnknownVis(u8),
+    /// The recording ended before a complete line was received.
+    ImageTooShort,
+    /// The encoder has no line format for this mode yet; holds the mode's name.
+    UnsupportedMode(String),
+    /// A TLE could not be read, or the satellite could not be placed at the time asked for.
+    Orbit(String),
+    /// A template or sidecar file could not be read or written as JSON.
+    Json(serde_json::Error),
+    /// A font for the text overlay could not be loaded.
+    Font(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{e}"),
+            Error::UnsupportedWav(reason) => write!(f, "unsupported WAV file: {reason}"),
+            Error::UnsupportedAudio(reason) => write!(f, "unsupported audio file: {reason}"),
+            Error::Image(e) => write!(f, "{e}"),
+            Error::NoSignal => write!(f, "no SSTV signal found"),
+            Error::NoVis => write!(f, "no VIS code found"),
+            Error::VisParity(code) => write!(f, "VIS code {code} failed its parity check"),
+            Error::UnknownVis(code) => write!(f, "VIS code {code} is not a supported mode"),
+            Error::ImageTooShort => write!(f, "the recording ended before a complete line"),
+            Error::UnsupportedMode(mode) => write!(f, "the encoder cannot send {mode} yet"),
+            Error::Orbit(reason) => write!(f, "cannot predict the satellite pass: {reason}"),
+            Error::Json(e) => write!(f, "{e}"),
+            Error::Font(reason) => write!(f, "cannot load the font: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Image(e) => Some(e),
+            Error::Json(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<hound::Error> for Error {
+    fn from(e: hound::Error) -> Self {
+        match e {
+            hound::Error::IoError(e) => Error::Io(e),
+            e => Error::UnsupportedWav(e.to_string()),
+        }
+    }
+}
+
+impl From<symphonia::core::errors::Error> for Error {
+    fn from(e: symphonia::core::errors::Error) -> Self {
+        match e {
+            symphonia::core::errors::Error::IoError(e) => Error::Io(e),
+            e => Error::UnsupportedAudio(e.to_string()),
+        }
+    }
+}
+
+impl From<image::ImageError> for Error {
+    fn from(e: image::ImageError) -> Self {
+        match e {
+            image::ImageError::IoError(e) => Error::Io(e),
+            e => Error::Image(e),
+        }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        if e.is_io() {
+            Error::Io(e.into())
+        } else {
+            Error::Json(e)
+        }
+    }
+}
+
+// Encoders used directly rather than through `image` report their failures as `image` would.
+fn encoding_error(format: image::ImageFormat, e: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> Error {
+    Error::Image(image::ImageError::Encoding(image::error::EncodingError::new(format.into(), e)))
+}
+
+impl From<png::EncodingError> for Error {
+    fn from(e: png::EncodingError) -> Self {
+        match e {
+            png::EncodingError::IoError(e) => Error::Io(e),
+            e => encoding_error(image::ImageFormat::Png, e),
+        }
+    }
+}
+
+impl From<tiff::TiffError> for Error {
+    fn from(e: tiff::TiffError) -> Self {
+        match e {
+            tiff::TiffError::IoError(e) => Error::Io(e),
+            e => encoding_error(image::ImageFormat::Tiff, e),
+        }
+    }
+}
@@ -13,14 +13,12 @@ pub fn fft(time_samples: Vec<Complex<f32>>) -> Vec<Complex<f32>> {
         let ceil_n: usize = 2.0_f32.powf(f32::ceil(log_n)) as usize;
 
         new_samples = Vec::with_capacity(ceil_n);
-        for i in 0..n as usize {
-            new_samples.push(time_samples[i]);
-        }
+        new_samples.extend(time_samples);
         for _i in n as usize ..(ceil_n) {
             new_samples.push(c32(0.0, 0.0));
         }
     }
-    return subdivide(new_samples)
+    subdivide(new_samples)
 }
 
 fn subdivide(samples: Vec<Complex<f32>>) -> Vec<Complex<f32>> {
@@ -55,7 +53,7 @@ fn subdivide(samples: Vec<Complex<f32>>) -> Vec<Complex<f32>> {
 
     let freq_bins: Vec<Complex<f32>> =[f_bins_low,f_bins_high].concat();
 
-    return freq_bins
+    freq_bins
 }
 
 pub fn ifft(samples: Vec<Complex<f32>>) -> Vec<Complex<f32>> {
@@ -80,7 +78,7 @@ pub fn ifft(samples: Vec<Complex<f32>>) -> Vec<Complex<f32>> {
         res_vec.push(z.conj()/(n as f32));
     }
 
-    return res_vec
+    res_vec
 
 }
 
@@ -90,9 +88,8 @@ pub fn hilbert(samples: Vec<Complex<f32>>, f_samp: f32, f_low: f32, f_high: f32)
 
     let mut hilbert: Vec<Complex<f32>> = Vec::with_capacity(hfft_len);
 
-    for i in 0..hfft_len {
+    for (i, &z) in hfft.iter().enumerate() {
 
-        let z = hfft[i];
         let mut h: f32 = 1.0;
 
         if i > 0 && i < (hfft_len/2) {
@@ -104,9 +101,38 @@ pub fn hilbert(samples: Vec<Complex<f32>>, f_samp: f32, f_low: f32, f_high: f32)
         hilbert.push(c32(z.re * h, z.im * h));
     }
 
-    let hifft = ifft(bp_filter(hilbert, f_samp, f_low, f_high));
+    ifft(bp_filter(hilbert, f_samp, f_low, f_high))
+}
+
+/// Estimates the signal to noise ratio in dB of the SSTV band (1100 to 2300 Hz), taking the
+/// noise density from the quiet parts of the audio channel either side of it.
+pub fn snr(samples: Vec<Complex<f32>>, f_samp: f32) -> Option<f32> {
+    let spectrum = fft(samples);
+    let bin_width = f_samp / spectrum.len() as f32;
+
+    let mut signal: f64 = 0.0;
+    let mut signal_bins = 0;
+    let mut noise: f64 = 0.0;
+    let mut noise_bins = 0;
+
+    for (i, z) in spectrum.iter().take(spectrum.len() / 2).enumerate() {
+        let f = i as f32 * bin_width;
+        let power = z.norm_sqr() as f64;
+        if (1100.0..=2300.0).contains(&f) {
+            signal += power;
+            signal_bins += 1;
+        } else if (400.0..=1000.0).contains(&f) || (2500.0..=3500.0).contains(&f) {
+            noise += power;
+            noise_bins += 1;
+        }
+    }
 
-    return  hifft;
+    if noise_bins == 0 || noise <= 0.0 {
+        return None
+    }
+    let in_band_noise = noise / noise_bins as f64 * signal_bins as f64;
+    let signal = signal - in_band_noise;
+    (signal > 0.0).then(|| (10.0 * f64::log10(signal / in_band_noise)) as f32)
 }
 
 fn bp_filter(samples: Vec<Complex<f32>>, f_samp: f32, f_low: f32, f_high: f32) -> Vec<Complex<f32>> {
@@ -127,5 +153,5 @@ fn bp_filter(samples: Vec<Complex<f32>>, f_samp: f32, f_low: f32, f_high: f32) -
 
     }
 
-    return filtered_samps
+    filtered_samps
 }
\ No newline at end of file
@@ -0,0 +1,140 @@
+/// Smoothing applied to the frequency track after demodulation and before `decode_image` looks for syncs.
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub enum PostFilter {
+    None,
+    MovingAverage,
+    Median,
+    LowPass,
+}
+
+impl std::fmt::Display for PostFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let label = match self {
+            PostFilter::None => "None",
+            PostFilter::MovingAverage => "Moving Average",
+            PostFilter::Median => "Median",
+            PostFilter::LowPass => "Low Pass",
+        };
+        write!(f, "{label}")
+    }
+}
+
+impl PostFilter {
+    /// Filters `freqs`, sizing the window (or cutoff) from the duration of one pixel in seconds.
+    pub fn apply(&self, freqs: &[f32], f_samp: f32, pixel_time: f32) -> Vec<f32> {
+        let window = usize::max(1, f32::round(pixel_time * f_samp) as usize);
+
+        match self {
+            PostFilter::None => freqs.to_vec(),
+            PostFilter::MovingAverage => moving_average(freqs, window),
+            PostFilter::Median => median(freqs, window),
+            PostFilter::LowPass => low_pass(freqs, f_samp, 0.5 / pixel_time),
+        }
+    }
+}
+
+fn moving_average(freqs: &[f32], window: usize) -> Vec<f32> {
+    let half = window / 2;
+    let mut prefix: Vec<f64> = Vec::with_capacity(freqs.len() + 1);
+    prefix.push(0.0);
+    for &f in freqs {
+        prefix.push(prefix[prefix.len() - 1] + f as f64);
+    }
+
+    (0..freqs.len()).map(|i| {
+        let start = i.saturating_sub(half);
+        let end = usize::min(freqs.len(), i + window - half);
+        ((prefix[end] - prefix[start]) / (end - start) as f64) as f32
+    }).collect()
+}
+
+fn median(freqs: &[f32], window: usize) -> Vec<f32> {
+    let half = window / 2;
+    let mut sorted: Vec<f32> = Vec::with_capacity(window);
+
+    (0..freqs.len()).map(|i| {
+        let start = i.saturating_sub(half);
+        let end = usize::min(freqs.len(), i + window - half);
+        sorted.clear();
+        sorted.extend_from_slice(&freqs[start..end]);
+        sorted.sort_unstable_by(f32::total_cmp);
+        sorted[sorted.len() / 2]
+    }).collect()
+}
+
+// Single-pole IIR run forwards then backwards, so sync edges are not delayed.
+fn low_pass(freqs: &[f32], f_samp: f32, cutoff: f32) -> Vec<f32> {
+    let alpha = 1.0 - f32::exp(-2.0 * std::f32::consts::PI * cutoff / f_samp);
+    let mut out = freqs.to_vec();
+
+    if let Some(&first) = out.first() {
+        let mut y = first;
+        for v in out.iter_mut() {
+            y += alpha * (*v - y);
+            *v = y;
+        }
+    }
+    if let Some(&last) = out.last() {
+        let mut y = last;
+        for v in out.iter_mut().rev() {
+            y += alpha * (*v - y);
+            *v = y;
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const F_SAMP: f32 = 22050.0;
+    // Martin M1, ten samples a pixel at the working rate.
+    const PIXEL_TIME: f32 = 0.4576 / 1000.0;
+
+    // Black for 200 samples, then white for 200.
+    fn step() -> Vec<f32> {
+        (0..400).map(|i| if i < 200 { 1500.0 } else { 2300.0 }).collect()
+    }
+
+    fn assert_step_kept(filter: PostFilter) {
+        let out = filter.apply(&step(), F_SAMP, PIXEL_TIME);
+        assert_eq!(out.len(), 400);
+        for i in (0..150).chain(250..400) {
+            let level = if i < 200 { 1500.0 } else { 2300.0 };
+            assert!((out[i] - level).abs() < 1.0, "{filter}: {} at sample {i}", out[i]);
+        }
+        // Not shifted in time: the edge is still half way up where it was.
+        assert!((out[199] + out[200] - 3800.0).abs() < 200.0, "{filter}: edge at {} and {}", out[199], out[200]);
+    }
+
+    #[test]
+    fn every_filter_keeps_a_step() {
+        for filter in [PostFilter::None, PostFilter::MovingAverage, PostFilter::Median, PostFilter::LowPass] {
+            assert_step_kept(filter);
+        }
+    }
+
+    #[test]
+    fn median_removes_a_spike_the_average_only_spreads() {
+        let mut freqs = vec![1900.0; 100];
+        freqs[50] = 3000.0;
+
+        let median = PostFilter::Median.apply(&freqs, F_SAMP, PIXEL_TIME);
+        assert!(median.iter().all(|&f| f == 1900.0));
+
+        let average = PostFilter::MovingAverage.apply(&freqs, F_SAMP, PIXEL_TIME);
+        assert!((average[50] - 2010.0).abs() < 1.0, "{}", average[50]);
+        assert!((average.iter().sum::<f32>() - freqs.iter().sum::<f32>()).abs() < 1.0);
+    }
+
+    #[test]
+    fn low_pass_smooths_noise_above_the_pixel_rate() {
+        // Alternating samples are at the Nyquist frequency, far above the cutoff.
+        let freqs: Vec<f32> = (0..400).map(|i| if i % 2 == 0 { 1800.0 } else { 2000.0 }).collect();
+        let out = PostFilter::LowPass.apply(&freqs, F_SAMP, PIXEL_TIME);
+        assert!(out[50..350].iter().all(|f| (f - 1900.0).abs() < 20.0));
+    }
+}
@@ -1,5 +1,38 @@
-use egui::ColorImage;
-use crate::Mode;
+use image::RgbImage;
+use crate::{Error, Mode, Result};
+use crate::filter::PostFilter;
+
+pub struct DecodeSettings {
+    pub post_filter: PostFilter,
+    /// Predict each sync from the mode's line period and free-run through ones that fade out.
+    pub flywheel: bool,
+    /// Estimate the receiver's tuning error and shift the track back onto the standard tones.
+    pub afc: bool,
+}
+
+impl Default for DecodeSettings {
+    fn default() -> Self {
+        Self {
+            post_filter: PostFilter::LowPass,
+            flywheel: true,
+            afc: true,
+        }
+    }
+}
+
+pub struct DecodedImage {
+    pub image: RgbImage,
+    /// Confidence of the sync pulse that started each line, from 0 (noise) to 1 (ideal pulse).
+    pub sync_confidence: Vec<f32>,
+    /// Tuning error in Hz that was removed from the track, if one was measured.
+    pub freq_offset: Option<f32>,
+    /// VIS code sent after the leader, if it was read with correct parity.
+    pub vis: Option<u8>,
+    /// Callsign sent as an FSK ID after the picture, if there was one.
+    pub fsk_id: Option<String>,
+    /// Line period measured between the first and last detected syncs, in seconds.
+    pub line_time: Option<f32>,
+}
 
 enum State {
     Ready,
@@ -9,10 +42,347 @@ enum State {
 }
 
 fn tolerance(value: f32, target: f32, tol: f32) -> bool {
-    return f32::abs(value-target) <= tol
+    f32::abs(value-target) <= tol
+}
+
+// Mean of `values[i..i+len]` for every `i`, shortened at the end of the slice.
+fn running_mean(values: &[f32], len: usize) -> Vec<f32> {
+    let mut prefix: Vec<f64> = Vec::with_capacity(values.len() + 1);
+    prefix.push(0.0);
+    for &v in values {
+        prefix.push(prefix[prefix.len() - 1] + v as f64);
+    }
+
+    (0..values.len()).map(|i| {
+        let end = usize::min(values.len(), i + len);
+        ((prefix[end] - prefix[i]) / (end - i) as f64) as f32
+    }).collect()
+}
+
+// Mean of the `len` values centred on each sample.
+fn centred_mean(values: &[f32], len: usize) -> Vec<f32> {
+    let half = len / 2;
+    let forward = running_mean(values, len);
+    (0..values.len()).map(|i| forward[i.saturating_sub(half)]).collect()
+}
+
+/// Correlates the frequency track against an ideal 1200 Hz pulse of the mode's sync length.
+/// Returns the start of every pulse that clears a threshold set from the local noise floor, with its confidence.
+fn find_syncs(frequency_samples: &[f32], f_samp: f32, mode: &Mode) -> Vec<(usize, f32)> {
+    let sync_len = usize::max(1, f32::round(mode.sync_time() * f_samp) as usize);
+    let min_spacing = match mode.line_time() {
+        Some(line_time) => (0.5 * line_time * f_samp) as usize,
+        None => 10 * sync_len,
+    };
+
+    // 1 for a sample right on the sync tone, falling to 0 at the black level.
+    let matched: Vec<f32> = frequency_samples.iter()
+        .map(|&f| f32::max(0.0, 1.0 - f32::abs(f - 1200.0) / 300.0))
+        .collect();
+    let correlation = running_mean(&matched, sync_len);
+
+    // Syncs take up only a few percent of a line, so a long average tracks the noise floor.
+    let floor = centred_mean(&correlation, 40 * sync_len);
+    let deviation: Vec<f32> = correlation.iter().zip(&floor).map(|(c, m)| f32::abs(c - m)).collect();
+    let spread = centred_mean(&deviation, 40 * sync_len);
+
+    // A near perfect pulse always counts, even where the VIS code has pushed the floor up.
+    let threshold = |i: usize| f32::min(0.85, f32::max(floor[i] + 0.5 * (1.0 - floor[i]), floor[i] + 4.0 * spread[i]));
+
+    let mut syncs: Vec<(usize, f32)> = Vec::new();
+    let mut i = 0;
+    while i < correlation.len() {
+        if correlation[i] >= threshold(i) {
+            let mut end = i;
+            let mut peak = i;
+            while end < correlation.len() && correlation[end] >= threshold(end) {
+                if correlation[end] > correlation[peak] {
+                    peak = end;
+                }
+                end += 1;
+            }
+            // A pulse longer than a sync (the VIS stop bit runs straight into the first one) is
+            // placed by where it ends, since that is where the line starts.
+            let start = usize::max(peak, end.saturating_sub(sync_len / 2));
+            let confidence = ((correlation[peak] - floor[peak]) / (1.0 - floor[peak])).clamp(0.0, 1.0);
+            syncs.push((start, confidence));
+            i = start + min_spacing;
+        } else {
+            i += 1;
+        }
+    }
+
+    syncs
+}
+
+fn median(values: &mut [f32]) -> f32 {
+    values.sort_unstable_by(f32::total_cmp);
+    values[values.len() / 2]
+}
+
+// Mean of every block of `block_len` samples, or `None` where the block is not a steady tone within `range` Hz of `target`.
+fn steady_tones(frequency_samples: &[f32], block_len: usize, target: f32, range: f32) -> Vec<Option<f32>> {
+    frequency_samples.chunks_exact(block_len).map(|block| {
+        let mean = block.iter().sum::<f32>() / block_len as f32;
+        let deviation = f32::sqrt(block.iter().map(|f| (f - mean) * (f - mean)).sum::<f32>() / block_len as f32);
+        (tolerance(mean, target, range) && deviation < 30.0).then_some(mean)
+    }).collect()
+}
+
+/// Measures how far the receiver is mistuned from the 1900 Hz leader tone, falling back to the
+/// 1200 Hz sync pulses when the leader was missed.
+pub fn estimate_offset(frequency_samples: &[f32], f_samp: f32, mode: &Mode) -> Option<f32> {
+    // The leader is the first unbroken 1900 Hz tone of at least 100 ms; bright image areas
+    // can hold steady near 1900 Hz too, but never before the leader.
+    let leader_block = usize::max(1, (0.01 * f_samp) as usize);
+    let mut run: Vec<f32> = Vec::new();
+    for tone in steady_tones(frequency_samples, leader_block, 1900.0, 150.0) {
+        match tone {
+            Some(mean) => run.push(mean),
+            None if run.len() >= 10 => break,
+            None => run.clear(),
+        }
+    }
+    if run.len() >= 10 {
+        return Some(median(&mut run) - 1900.0)
+    }
+
+    // Picture content stays above 1500 Hz, so a steady tone well below it is a sync pulse, a VIS bit
+    // or a break in the leader. The VIS code stays this low from its start bit to its stop bit and
+    // so makes one run far longer than a sync, as do the 10 ms breaks, so only runs about a sync
+    // long are measured. The window is wide since this is only reached when the leader was missed.
+    let sync_len = mode.sync_time() * f_samp;
+    let low = |f: f32| tolerance(f, 1200.0, 300.0);
+    let mut pulses: Vec<f32> = Vec::new();
+    let mut i = 0;
+    while i < frequency_samples.len() {
+        if !low(frequency_samples[i]) {
+            i += 1;
+            continue
+        }
+        let start = i;
+        while i < frequency_samples.len() && low(frequency_samples[i]) {
+            i += 1;
+        }
+        let run = i - start;
+        if (0.7 * sync_len..=1.3 * sync_len).contains(&(run as f32)) {
+            let middle = &frequency_samples[start + run / 4..i - run / 4];
+            if let Some(Some(tone)) = steady_tones(middle, middle.len(), 1200.0, 300.0).first() {
+                pulses.push(*tone);
+            }
+        }
+    }
+    if pulses.len() >= 5 {
+        return Some(median(&mut pulses) - 1200.0)
+    }
+
+    None
+}
+
+/// Accepts only syncs that land within `window` samples of where the line period predicts them,
+/// and inserts a zero confidence sync at the prediction when none does, so lost lines keep their place.
+fn flywheel(syncs: &[(usize, f32)], start: usize, line_period: f32, window: usize, len: usize, max_syncs: usize) -> Vec<(usize, f32)> {
+    let candidates: Vec<(usize, f32)> = syncs.iter().copied().filter(|s| s.0 >= start).collect();
+
+    // Lock on to the first sync that has another one a line period later; the breaks
+    // between the leader tones and the VIS bits do not. With nothing to lock on to, the
+    // detected syncs are all there is to go on.
+    let anchor = candidates.iter().position(|a| {
+        candidates.iter().any(|b| f32::abs(b.0 as f32 - a.0 as f32 - line_period) <= window as f32)
+    });
+    let Some(anchor) = anchor else {
+        return candidates
+    };
+
+    let mut locked = vec![candidates[anchor]];
+    let mut position = candidates[anchor].0 as f32;
+    let mut period = line_period;
+    let mut last_detected = 0;
+    let mut missed = 0;
+    let mut c = anchor + 1;
+
+    while locked.len() < max_syncs {
+        let predicted = position + period;
+        if predicted as usize >= len {
+            break
+        }
+
+        while c < candidates.len() && (candidates[c].0 as f32) < predicted - window as f32 {
+            c += 1;
+        }
+        let mut best: Option<(usize, f32)> = None;
+        while c < candidates.len() && (candidates[c].0 as f32) <= predicted + window as f32 {
+            if best.is_none_or(|b| candidates[c].1 > b.1) {
+                best = Some(candidates[c]);
+            }
+            c += 1;
+        }
+
+        match best {
+            Some(sync) => {
+                // Follow small clock errors in the recording, but only across consecutive detections.
+                if missed == 0 {
+                    period += 0.1 * ((sync.0 as f32 - position) - period);
+                }
+                position = sync.0 as f32;
+                locked.push(sync);
+                last_detected = locked.len() - 1;
+                missed = 0;
+            }
+            None => {
+                position = predicted;
+                locked.push((f32::round(predicted) as usize, 0.0));
+                missed += 1;
+                // A long run without syncs is the end of the transmission, not fading.
+                if missed > 20 {
+                    break
+                }
+            }
+        }
+    }
+
+    // Keep one predicted sync after the last detection so its line is still closed off.
+    locked.truncate(last_detected + 2);
+    locked
+}
+
+// Median frequency of the middle of the slot `[start, start + len)`, away from the tone changes.
+fn slot_tone(frequency_samples: &[f32], start: usize, len: usize) -> Option<f32> {
+    let margin = len / 5;
+    let mut slot = frequency_samples.get(start + margin..start + len - margin)?.to_vec();
+    if slot.is_empty() {
+        return None
+    }
+    Some(median(&mut slot))
 }
 
-pub fn decode_image(frequency_samples: Vec<f32>, mode: Mode) -> ColorImage {
+/// Reads the VIS code: a 30 ms 1200 Hz start bit after the leader, then seven data bits
+/// (1100 Hz for one, 1300 Hz for zero, least significant first) and an even parity bit.
+pub fn decode_vis(frequency_samples: &[f32], f_samp: f32) -> Result<u8> {
+    let block = usize::max(1, (0.01 * f_samp) as usize);
+    let leader = (0..frequency_samples.len() / block)
+        .find(|&b| slot_tone(frequency_samples, b * block, block).is_some_and(|f| tolerance(f, 1900.0, 50.0)))
+        .ok_or(Error::NoSignal)? * block;
+    let bit_len = (0.03 * f_samp) as usize;
+    let search_end = usize::min(frequency_samples.len(), leader + (2.0 * f_samp) as usize);
+
+    // The 10 ms break between the leader tones also drops to 1200 Hz, so check each drop for a full length start bit.
+    for i in (leader + 1)..search_end {
+        if !(frequency_samples[i] < 1550.0 && frequency_samples[i - 1] >= 1550.0) {
+            continue
+        }
+        if !slot_tone(frequency_samples, i, bit_len).is_some_and(|f| tolerance(f, 1200.0, 60.0)) {
+            continue
+        }
+
+        let mut code: u8 = 0;
+        let mut ones = 0;
+        for bit in 0..8 {
+            let tone = slot_tone(frequency_samples, i + (bit + 1) * bit_len, bit_len).ok_or(Error::NoVis)?;
+            let one = if tolerance(tone, 1100.0, 60.0) {
+                true
+            } else if tolerance(tone, 1300.0, 60.0) {
+                false
+            } else {
+                return Err(Error::NoVis)
+            };
+            if one {
+                ones += 1;
+                if bit < 7 {
+                    code |= 1 << bit;
+                }
+            }
+        }
+        if ones % 2 != 0 {
+            return Err(Error::VisParity(code))
+        }
+        return Ok(code)
+    }
+
+    Err(Error::NoVis)
+}
+
+/// Reads an MMSSTV style FSK ID after `from`: 22 ms bits of 1900 Hz for one and 2100 Hz for zero,
+/// sent as six bit characters offset from 0x20, least significant bit first, between a
+/// 0x20 0x2A header and a 0x01 terminator.
+pub fn decode_fsk_id(frequency_samples: &[f32], f_samp: f32, from: usize) -> Option<String> {
+    let bit_len = (0.022 * f_samp) as usize;
+    let step = usize::max(1, (0.001 * f_samp) as usize);
+    let search_end = usize::min(frequency_samples.len(), from + (5.0 * f_samp) as usize);
+
+    let bit = |start: usize| -> Option<u8> {
+        let tone = slot_tone(frequency_samples, start, bit_len)?;
+        if tolerance(tone, 1900.0, 80.0) {
+            Some(1)
+        } else if tolerance(tone, 2100.0, 80.0) {
+            Some(0)
+        } else {
+            None
+        }
+    };
+    let symbol = |start: usize| -> Option<u8> {
+        (0..6).try_fold(0, |value, b| Some(value | bit(start + b * bit_len)? << b))
+    };
+
+    for start in (from..search_end).step_by(step) {
+        if symbol(start) != Some(0x20) || symbol(start + 6 * bit_len) != Some(0x2A) {
+            continue
+        }
+
+        let mut callsign = String::new();
+        let mut position = start + 12 * bit_len;
+        while let Some(value) = symbol(position) {
+            if value == 0x01 || callsign.len() >= 16 {
+                break
+            }
+            callsign.push(char::from(value + 0x20));
+            position += 6 * bit_len;
+        }
+        let callsign = callsign.trim().to_string();
+        return (!callsign.is_empty()).then_some(callsign)
+    }
+
+    None
+}
+
+/// Works out the mode from the VIS code, for recordings whose mode is not known in advance.
+pub fn detect_mode(frequency_samples: &[f32], f_samp: f32) -> Result<Mode> {
+    // VIS bits last 30 ms, so smooth as if the pixels were a millisecond long.
+    let mut frequency_samples = PostFilter::LowPass.apply(frequency_samples, f_samp, 0.001);
+    // Only the leader is needed to measure the offset, so the mode passed here does not matter.
+    if let Some(offset) = estimate_offset(&frequency_samples, f_samp, &Mode::MartinM1) {
+        for f in frequency_samples.iter_mut() {
+            *f -= offset;
+        }
+    }
+    let vis = decode_vis(&frequency_samples, f_samp)?;
+    Mode::from_vis(vis).ok_or(Error::UnknownVis(vis))
+}
+
+// Line period from the first to the last detected sync, counting the whole periods between them.
+fn measure_line_time(syncs: &[(usize, f32)], f_samp: f32, line_time: f32) -> Option<f32> {
+    let first = syncs.iter().find(|s| s.1 > 0.0)?.0 as f32;
+    let last = syncs.iter().rev().find(|s| s.1 > 0.0)?.0 as f32;
+    let periods = f32::round((last - first) / (line_time * f_samp));
+    (periods >= 1.0).then(|| (last - first) / periods / f_samp)
+}
+
+pub fn decode_image(frequency_samples: Vec<f32>, f_samp: f32, mode: Mode, settings: &DecodeSettings) -> Result<DecodedImage> {
+
+    let mut frequency_samples = settings.post_filter.apply(&frequency_samples, f_samp, mode.pixel_time());
+
+    let freq_offset = if settings.afc { estimate_offset(&frequency_samples, f_samp, &mode) } else { None };
+    if let Some(offset) = freq_offset {
+        for f in frequency_samples.iter_mut() {
+            *f -= offset;
+        }
+    }
+
+    let mut syncs = find_syncs(&frequency_samples, f_samp, &mode);
+    if syncs.is_empty() {
+        return Err(Error::NoSignal)
+    }
+    let mut next_sync = 0;
 
     let mut width: usize = 320;
     let mut num_lines: usize =  256;
@@ -32,33 +402,52 @@ pub fn decode_image(frequency_samples: Vec<f32>, mode: Mode) -> ColorImage {
             width = 640;
         }
     }
-    width = width*n;
+    width *= n;
 
     let mut state = State::Ready;
     let mut row_buf: Vec<f32> = Vec::new();
     let mut lines: Vec<Vec<f32>> = Vec::new();
-    let max_blank = 50;
+    let mut sync_confidence: Vec<f32> = Vec::new();
+    let mut line_confidence: Option<f32> = None;
+    let max_blank = usize::max(1, f32::round(mode.sync_time() * f_samp) as usize);
     let mut blank = max_blank;
 
+    if settings.flywheel
+        && let Some(line_time) = mode.line_time()
+        && let Some(start) = frequency_samples.iter().position(|&f| tolerance(f, 1900.0, 5.0)) {
+        syncs = flywheel(&syncs, start, line_time * f_samp, max_blank, frequency_samples.len(), num_lines + 1);
+    }
+
+    let vis = decode_vis(&frequency_samples, f_samp).ok();
+    let line_time = mode.line_time().and_then(|line_time| measure_line_time(&syncs, f_samp, line_time));
+    let fsk_id = syncs.last().and_then(|s| decode_fsk_id(&frequency_samples, f_samp, s.0));
+
     for (i, &f) in frequency_samples.iter().enumerate() {
 
         if i == frequency_samples.len() - 1 {
             state = State::Done;
         }
+        while next_sync < syncs.len() && syncs[next_sync].0 < i {
+            next_sync += 1;
+        }
+        let sync = syncs.get(next_sync).filter(|s| s.0 == i);
         match state {
             State::Ready => {
                 if tolerance(f, 1900.0, 5.0) {state = State::SyncWait}
             }
             State::SyncWait => {
-                if tolerance(f, 1200.0, 20.0) {
-                    if row_buf.len() >= width {
-                        state = State::SyncStart;
-                        blank = max_blank; 
+                if let Some(&(_, confidence)) = sync {
+                    state = State::SyncStart;
+                    blank = max_blank;
+                    if let Some(previous) = line_confidence
+                        && row_buf.len() >= width {
                         lines.push(row_buf.clone());
+                        sync_confidence.push(previous);
                         if lines.len() > num_lines {
                             state = State::Done
                         }
                     }
+                    line_confidence = Some(confidence);
                     row_buf.clear();
                 } else {
                     let l = f32::round(f32::abs((255.0)*(f-1500.0)/(2300.0-1500.0)));
@@ -69,27 +458,29 @@ pub fn decode_image(frequency_samples: Vec<f32>, mode: Mode) -> ColorImage {
                 if blank > 0 {
                     blank -= 1;
                 } else {
-                    if tolerance(f, 1200.0, 300.0) == false {state = State::SyncWait}
+                    if !tolerance(f, 1200.0, 300.0) {state = State::SyncWait}
                 }
                 
             }
             State::Done => {
 
-                let mut data_grid: Vec<u8> = Vec::new();
+                if lines.is_empty() {
+                    return Err(Error::ImageTooShort)
+                }
 
-                println!("Decoding Complete, {} Lines Found", lines.len());
+                let mut data_grid: Vec<u8> = Vec::new();
 
                 for line in lines.clone() {
                     if line.len() >= width {
                         for i in 0..width {
                             let line_len = line.len();
-                            let line_slice = &line[((i*(line_len-1))/width)..((((i+1)*(line_len-1))/width))];
+                            let line_slice = &line[((i*(line_len-1))/width)..(((i+1)*(line_len-1))/width)];
                             let mut l: f32 = 0.0;
-                            if line_slice.len() > 0 {
+                            if !line_slice.is_empty() {
                                 for v in line_slice {
                                   l += v
                                 }
-                                l = l/(line_slice.len() as f32);
+                                l /= line_slice.len() as f32 ;
                             }
                             let y = f32::round(l) as u8;
                             data_grid.push(y);
@@ -102,18 +493,18 @@ pub fn decode_image(frequency_samples: Vec<f32>, mode: Mode) -> ColorImage {
                     }
                 }
                 
-                let mut pixels: Vec<egui::Color32> = Vec::new();
+                let mut pixels: Vec<u8> = Vec::new();
 
                 match mode {
                     Mode::RAW => {
                         for val in data_grid {
-                            pixels.push(egui::Color32::from_rgb(val,val,val));
+                            pixels.extend([val, val, val]);
                         }
                     }
                     Mode::MartinM1 | Mode::MartinM2 => {
                         for (i, &val) in data_grid.iter().enumerate() {
                             if i % (width) < (width/3) {
-                                pixels.push(egui::Color32::from_rgb( data_grid[i+(2*width/3)],val, data_grid[i+(width/3)]))
+                                pixels.extend([data_grid[i+(2*width/3)], val, data_grid[i+(width/3)]])
                             }
                         }   
                     }
@@ -125,24 +516,93 @@ pub fn decode_image(frequency_samples: Vec<f32>, mode: Mode) -> ColorImage {
                                 let r = f32::round(val as f32 + (1.402*(cr - 128.0))).clamp(0.0, 255.0) as u8;
                                 let g = f32::round(val as f32 - (0.344*(cb - 128.0)) - (0.714*(cr - 128.0))).clamp(0.0, 255.0) as u8;
                                 let b = f32::round(val as f32 + (1.772*(cb - 128.0))).clamp(0.0, 255.0) as u8;
-                                pixels.push(egui::Color32::from_rgb(r,g,b))
+                                pixels.extend([r, g, b])
                             }
                         }
                     }
                 }
 
-                let new_image = egui::ColorImage {
-                    size: [width/n as usize, lines.len() as usize],
-                    source_size: egui::Vec2 { x: (width/n) as f32, y: (lines.len()) as f32 },
-                    pixels,
-                };
+                let new_image = RgbImage::from_raw((width/n) as u32, lines.len() as u32, pixels).ok_or(Error::ImageTooShort)?;
 
-                return new_image
+                return Ok(DecodedImage { image: new_image, sync_confidence, freq_offset, vis, fsk_id, line_time })
             }
         }
 
 
     }
 
-    return ColorImage::example();
-}
\ No newline at end of file
+    // Only reached when there were no samples at all.
+    Err(Error::NoSignal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matched_filter_finds_sync_pulses() {
+        let f_samp = 22050.0;
+        let mode = Mode::MartinM1;
+        let sync_len = f32::round(mode.sync_time() * f_samp) as usize;
+        let period = (mode.line_time().unwrap() * f_samp) as usize;
+        let starts: Vec<usize> = (0..6).map(|line| 2000 + line * period).collect();
+
+        // Picture content sweeping over the whole 1500 to 2300 Hz range, with a sync starting each line.
+        let mut freqs: Vec<f32> = (0..starts[5] + period).map(|i| 1900.0 + 400.0 * f32::sin(i as f32 * 0.37)).collect();
+        for (line, &start) in starts.iter().enumerate() {
+            // The fourth pulse arrives 90 Hz off, as from a weak or mistuned signal.
+            let tone = if line == 3 { 1290.0 } else { 1200.0 };
+            freqs[start..start + sync_len].fill(tone);
+        }
+
+        let syncs = find_syncs(&freqs, f_samp, &mode);
+        assert_eq!(syncs.len(), starts.len(), "{syncs:?}");
+        for (line, (&(found, confidence), &start)) in syncs.iter().zip(&starts).enumerate() {
+            assert!(found.abs_diff(start) <= sync_len / 10, "line {line} found at {found}, sent at {start}");
+            if line == 3 {
+                assert!((0.5..0.8).contains(&confidence), "line {line} confidence {confidence}");
+            } else {
+                assert!(confidence > 0.9, "line {line} confidence {confidence}");
+            }
+        }
+    }
+
+    #[test]
+    fn offset_is_measured_from_syncs_without_a_leader() {
+        let f_samp = 22050.0;
+        let mode = Mode::MartinM1;
+        let sync_len = f32::round(mode.sync_time() * f_samp) as usize;
+        let period = (mode.line_time().unwrap() * f_samp) as usize;
+        let bit_len = (0.03 * f_samp) as usize;
+
+        for offset in [250.0, -220.0] {
+            // A VIS code with no leader before it: start bit, the bits of 44 with even parity, stop bit.
+            let mut freqs: Vec<f32> = Vec::new();
+            for tone in [1200.0, 1300.0, 1300.0, 1100.0, 1100.0, 1300.0, 1100.0, 1300.0, 1100.0, 1200.0] {
+                freqs.extend(std::iter::repeat_n(tone + offset, bit_len));
+            }
+            for line in 0..20 {
+                let start = freqs.len();
+                freqs.extend((0..period).map(|i| 1900.0 + offset + 400.0 * f32::sin((start + i) as f32 * 0.37)));
+                freqs[start..start + sync_len].fill(1200.0 + offset + if line % 2 == 0 { 3.0 } else { -3.0 });
+            }
+
+            let estimate = estimate_offset(&freqs, f_samp, &mode).unwrap();
+            assert!((estimate - offset).abs() < 5.0, "measured {estimate} Hz for {offset} Hz");
+        }
+    }
+
+    #[test]
+    fn flywheel_fills_a_missed_line() {
+        let syncs = [(1000, 0.9), (2000, 0.8), (4002, 0.7), (4998, 0.9)];
+        let locked = flywheel(&syncs, 0, 1000.0, 20, 5500, 10);
+        assert_eq!(locked, vec![(1000, 0.9), (2000, 0.8), (3000, 0.0), (4002, 0.7), (4998, 0.9)]);
+    }
+
+    #[test]
+    fn flywheel_keeps_syncs_it_cannot_lock_on_to() {
+        let syncs = [(100, 0.4), (1700, 0.6), (2300, 0.5)];
+        assert_eq!(flywheel(&syncs, 0, 1000.0, 20, 5000, 10), syncs.to_vec());
+        assert_eq!(flywheel(&syncs, 1000, 1000.0, 20, 5000, 10), syncs[1..].to_vec());
+    }
+}
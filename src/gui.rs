@@ -0,0 +1,19 @@
+//! Conversions between the library's images and egui's.
+
+use egui::ColorImage;
+use image::{Rgb, RgbImage};
+
+pub fn to_color_image(image: &RgbImage) -> ColorImage {
+    let size = [image.width() as usize, image.height() as usize];
+    ColorImage::from_rgb(size, image.as_raw())
+}
+
+/// Drops the alpha channel; egui keeps colours premultiplied, so transparent pixels come out black.
+pub fn from_color_image(image: &ColorImage) -> RgbImage {
+    let [width, height] = image.size;
+    let mut out = RgbImage::new(width as u32, height as u32);
+    for (pix, colour) in out.pixels_mut().zip(&image.pixels) {
+        *pix = Rgb([colour.r(), colour.g(), colour.b()]);
+    }
+    out
+}
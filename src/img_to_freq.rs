@@ -1,148 +1,625 @@
-use egui::ColorImage;
-use crate::Mode;
-
-pub fn encode(image_data: ColorImage, mode: Mode) -> Vec<i16> {
-    let mut freq_vec: Vec<f32> = Vec::new();
-    let vis: u8;
+use std::collections::VecDeque;
+use std::path::Path;
+use image::{ImageDecoder, Rgb, RgbImage};
+use crate::{Error, Mode, Result};
+use crate::audio::{BitDepth, Channels};
+
+/// How each change of tone is smoothed before it reaches the oscillator.
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub enum Shaping {
+    None,
+    RaisedCosine,
+    Gaussian,
+}
 
-    match mode {
-        Mode::MartinM1 => {
-            vis = 44;
-        }
-        Mode::MartinM2 => {
-            vis = 40;
-        }
-        Mode::RAW | Mode::PD120 | Mode::PD180 => {
-            vis = 0;
-        }
+impl std::fmt::Display for Shaping {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let label = match self {
+            Shaping::None => "None",
+            Shaping::RaisedCosine => "Raised Cosine",
+            Shaping::Gaussian => "Gaussian",
+        };
+        write!(f, "{label}")
     }
+}
+
+/// How a picture of any shape is brought to the mode's resolution.
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub enum Fit {
+    Stretch,
+    CentreCrop,
+    Letterbox,
+    Crop,
+}
 
-    let f_samp: f32 = 44100.0;
+impl std::fmt::Display for Fit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let label = match self {
+            Fit::Stretch => "Stretch",
+            Fit::CentreCrop => "Centre Crop",
+            Fit::Letterbox => "Letterbox",
+            Fit::Crop => "Crop Selection",
+        };
+        write!(f, "{label}")
+    }
+}
 
-    let mut add_freq = |freq: f32, time: f32| {
-        for _i in 0..f32::floor(f_samp*time) as i32 {
-            freq_vec.push(freq);
-        }
-    };
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub enum ResizeFilter {
+    Lanczos3,
+    CatmullRom,
+    Gaussian,
+    Nearest,
+}
 
-    // SSTV Structure
+impl std::fmt::Display for ResizeFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let label = match self {
+            ResizeFilter::Lanczos3 => "Lanczos3",
+            ResizeFilter::CatmullRom => "CatmullRom",
+            ResizeFilter::Gaussian => "Gaussian",
+            ResizeFilter::Nearest => "Nearest",
+        };
+        write!(f, "{label}")
+    }
+}
 
-    // Leader Tone
-    add_freq(1900.0, 0.3);
+impl ResizeFilter {
+    pub fn filter_type(&self) -> image::imageops::FilterType {
+        match self {
+            ResizeFilter::Lanczos3 => image::imageops::FilterType::Lanczos3,
+            ResizeFilter::CatmullRom => image::imageops::FilterType::CatmullRom,
+            ResizeFilter::Gaussian => image::imageops::FilterType::Gaussian,
+            ResizeFilter::Nearest => image::imageops::FilterType::Nearest,
+        }
+    }
+}
 
-    // Break
-    add_freq(1200.0, 0.01);
+#[derive(Clone)]
+pub struct EncodeSettings {
+    pub sample_rate: u32,
+    pub shaping: Shaping,
+    /// Time each tone change is spread over, in seconds.
+    pub transition_time: f32,
+    /// Peak output level in dBFS; how far it sits below 0 is the headroom.
+    pub level_db: f32,
+    pub bit_depth: BitDepth,
+    pub channels: Channels,
+    /// Silence before the leader tone and after the last line, in seconds.
+    pub lead_silence: f32,
+    pub trail_silence: f32,
+    pub fit: Fit,
+    pub filter: ResizeFilter,
+    /// Colour of the bars added by `Fit::Letterbox`.
+    pub fill: Rgb<u8>,
+    /// Source rectangle `[x, y, width, height]` used by `Fit::Crop`.
+    pub crop: Option<[u32; 4]>,
+}
 
-    // Leader Tone
-    add_freq(1900.0, 0.3);
+impl Default for EncodeSettings {
+    fn default() -> Self {
+        Self {
+            sample_rate: 44100,
+            shaping: Shaping::RaisedCosine,
+            transition_time: 0.1 / 1000.0,
+            level_db: -3.0,
+            bit_depth: BitDepth::Int16,
+            channels: Channels::Mono,
+            lead_silence: 0.0,
+            trail_silence: 0.0,
+            fit: Fit::CentreCrop,
+            filter: ResizeFilter::Lanczos3,
+            fill: Rgb([0, 0, 0]),
+            crop: None,
+        }
+    }
+}
 
-    // Break
-    add_freq(1200.0, 0.03);
+const TAU: f32 = 2.0 * std::f32::consts::PI;
+
+// Normalised smoothing kernel spanning `len` samples.
+fn shaping_kernel(shaping: &Shaping, len: usize) -> Vec<f32> {
+    let centre = (len - 1) as f32 / 2.0;
+    let kernel: Vec<f32> = (0..len).map(|i| {
+        let t = i as f32 - centre;
+        match shaping {
+            Shaping::None => 1.0,
+            Shaping::RaisedCosine => 0.5 + 0.5 * f32::cos(std::f32::consts::PI * t / (centre + 1.0)),
+            Shaping::Gaussian => f32::exp(-0.5 * (t / (len as f32 / 6.0)).powi(2)),
+        }
+    }).collect();
+    let sum: f32 = kernel.iter().sum();
+    kernel.into_iter().map(|k| k / sum).collect()
+}
 
-    // VIS Code
+// Leader, VIS code and stop bit, as (frequency, duration) pairs.
+fn header_tones(vis: u8) -> Vec<(f32, f32)> {
+    let mut tones = vec![
+        // Leader Tone
+        (1900.0, 0.3),
+        // Break
+        (1200.0, 0.01),
+        // Leader Tone
+        (1900.0, 0.3),
+        // Break
+        (1200.0, 0.03),
+    ];
+
+    // VIS Code, one bits at 1100 Hz and zero bits at 1300 Hz
     let mut parity: u8 = 0;
     for i in 0..7 {
         if (vis & (1 << i)) != 0 {
-            add_freq(1300.0, 0.03);
-            print!("1");
+            tones.push((1100.0, 0.03));
             parity += 1;
         } else {
-            add_freq(1100.0, 0.03);
-            print!("0")
+            tones.push((1300.0, 0.03));
         }
     }
 
-
     // Parity Bit
-    if parity % 2 == 0 {
-        add_freq(1300.0, 0.03);
+    if parity.is_multiple_of(2) {
+        tones.push((1300.0, 0.03));
     } else {
-        add_freq(1100.0, 0.03);
+        tones.push((1100.0, 0.03));
     }
 
     // Stop Bit
-    add_freq(1200.0, 0.03);
+    tones.push((1200.0, 0.03));
+    tones
+}
 
-    let img_dim: [usize; 2] = [image_data.width(), image_data.height()];
-    let old_pix_vec: Vec<egui::Color32> = image_data.pixels;
+/// Generates the transmission a sample at a time, mono at `settings.sample_rate` and full scale at ±1.0,
+/// so long modes at high rates can be written out progressively instead of held in memory.
+pub struct Encoder {
+    image: RgbImage,
+    mode: Mode,
+    f_samp: f32,
+    amplitude: f32,
+    // Tones still to send from the current line, and the next line to turn into tones.
+    tones: std::vec::IntoIter<(f32, f32)>,
+    next_line: usize,
+    num_lines: usize,
+    // Tones rarely last a whole number of samples, so track the ideal elapsed time and
+    // round only where each tone ends; otherwise every pixel loses a fraction and the lines slant.
+    clock: f64,
+    tone_freq: f32,
+    tone_end: usize,
+    produced: usize,
+    // Smoothing applied to the tone changes, over a window reaching half a kernel either side.
+    kernel: Vec<f32>,
+    window: VecDeque<f32>,
+    pulled: usize,
+    emitted: usize,
+    last_freq: f32,
+    phase: f32,
+    lead: usize,
+    trail: usize,
+}
 
-    match mode {
-        Mode::MartinM1 | Mode::MartinM2 => {
-            let num_lines: usize = 256;
-            let line_len: usize = 320;
+impl Encoder {
+    /// Fails with `Error::UnsupportedMode` for modes whose lines the encoder cannot generate yet.
+    pub fn new(image_data: &RgbImage, mode: Mode, settings: &EncodeSettings) -> Result<Self> {
+        let f_samp = settings.sample_rate as f32;
+        let (line_len, num_lines) = mode.resolution();
 
-            let pix_vec = resize(old_pix_vec, img_dim[0] as u32, line_len as u32, img_dim[1] as u32,num_lines as u32);
+        let num_lines = match mode {
+            Mode::MartinM1 | Mode::MartinM2 => num_lines - 1,
+            Mode::RAW | Mode::PD120 | Mode::PD180 => return Err(Error::UnsupportedMode(mode.to_string())),
+        };
 
-            let t_pix;
-            
-            if mode == Mode::MartinM1 {
-                t_pix = (146.432/1000.0) / (line_len as f32);
-            } else {
-                t_pix = (73.216/1000.0) / (line_len as f32);
+        let len = f32::round(settings.transition_time * f_samp) as usize | 1;
+        let kernel = if settings.shaping == Shaping::None || len <= 1 {
+            Vec::new()
+        } else {
+            shaping_kernel(&settings.shaping, len)
+        };
+
+        Ok(Self {
+            image: fit(image_data, line_len as u32, mode.resolution().1 as u32, settings),
+            tones: header_tones(mode.vis().unwrap_or(0)).into_iter(),
+            mode,
+            f_samp,
+            amplitude: 10f32.powf(settings.level_db / 20.0),
+            next_line: 0,
+            num_lines,
+            clock: 0.0,
+            tone_freq: 0.0,
+            tone_end: 0,
+            produced: 0,
+            kernel,
+            window: VecDeque::new(),
+            pulled: 0,
+            emitted: 0,
+            last_freq: 0.0,
+            phase: 0.0,
+            lead: f32::round(settings.lead_silence * f_samp) as usize,
+            trail: f32::round(settings.trail_silence * f_samp) as usize,
+        })
+    }
+
+    // Tones of one line of the picture.
+    fn line_tones(&self, line_num: usize) -> Vec<(f32, f32)> {
+        let (line_len, _) = self.mode.resolution();
+        let mut tones = Vec::new();
+
+        match self.mode {
+            Mode::MartinM1 | Mode::MartinM2 => {
+                let t_pix = if self.mode == Mode::MartinM1 {
+                    (146.432/1000.0) / (line_len as f32)
+                } else {
+                    (73.216/1000.0) / (line_len as f32)
+                };
+
+                tones.push((1200.0, 4.862/1000.0));
+                tones.push((1500.0, 0.572/1000.0));
+
+                // Red, green then blue scans, each followed by a porch.
+                for channel in 0..3 {
+                    for x in 0..line_len {
+                        let value = self.image.get_pixel(x as u32, line_num as u32)[channel] as f32 / 255.0;
+                        tones.push((value * 800.0 + 1500.0, t_pix));
+                    }
+                    tones.push((1500.0, 0.572/1000.0));
+                }
             }
+            Mode::RAW | Mode::PD120 | Mode::PD180 => {}
+        }
 
-            for line_num in 0..num_lines-1 {
+        tones
+    }
 
-                add_freq(1200.0, 4.862/1000.0);
-                add_freq(1500.0, 0.572/1000.0);
+    // Next sample of the unsmoothed frequency track.
+    fn next_freq(&mut self) -> Option<f32> {
+        while self.produced >= self.tone_end {
+            let (freq, time) = match self.tones.next() {
+                Some(tone) => tone,
+                None if self.next_line < self.num_lines => {
+                    self.tones = self.line_tones(self.next_line).into_iter();
+                    self.next_line += 1;
+                    continue
+                }
+                None => return None,
+            };
+            self.clock += time as f64;
+            self.tone_end = f64::round(self.clock * self.f_samp as f64) as usize;
+            self.tone_freq = freq;
+        }
+        self.produced += 1;
+        Some(self.tone_freq)
+    }
 
-                let line_buffer: Vec<egui::Color32> = pix_vec[line_num*line_len..(line_num+1)*line_len].to_vec();
-                let mut yuv_buffer: Vec<f32> = vec![0.0; line_len*3];
+    // Next track sample pulled into the smoothing window, repeating the last one past the end.
+    fn pull(&mut self) -> f32 {
+        if let Some(freq) = self.next_freq() {
+            self.pulled += 1;
+            self.last_freq = freq;
+        }
+        self.last_freq
+    }
 
-                for (i, pix) in line_buffer.iter().enumerate() {
-                    let r = pix.r() as f32 / 255.0;
-                    let g = pix.g() as f32 / 255.0;
-                    let b = pix.b() as f32 / 255.0;
-    
-                    yuv_buffer[i] = r;
-                    yuv_buffer[i + line_len] = g;
-                    yuv_buffer[i + (line_len * 2)] = b;
-                }
+    // Next sample of the smoothed frequency track.
+    fn next_shaped(&mut self) -> Option<f32> {
+        if self.kernel.is_empty() {
+            return self.next_freq()
+        }
 
-                for (i, f) in yuv_buffer.iter().enumerate() {
-                    add_freq(*f * 800.0 + 1500.0, t_pix);
-                    if (i+1) % line_len == 0 {
-                        add_freq(1500.0, 0.572/1000.0);
-                    }
-                }
+        let half = self.kernel.len() / 2;
+        if self.window.is_empty() {
+            // Before the start the first sample is repeated, as after the end the last one is.
+            let first = self.next_freq()?;
+            self.pulled = 1;
+            self.last_freq = first;
+            self.window.extend(std::iter::repeat_n(first, half + 1));
+            for _ in 0..half {
+                let freq = self.pull();
+                self.window.push_back(freq);
             }
         }
-        Mode::RAW | Mode::PD120 | Mode::PD180 => {
-            println!("How did you get here?");
+        if self.emitted == self.pulled {
+            return None
         }
+
+        let freq = self.kernel.iter().zip(&self.window).map(|(w, f)| w * f).sum();
+        self.emitted += 1;
+        self.window.pop_front();
+        let next = self.pull();
+        self.window.push_back(next);
+        Some(freq)
+    }
+
+    /// Number of image lines the transmission carries, which for the Martin modes is one short
+    /// of the mode's resolution.
+    pub fn lines(&self) -> usize {
+        self.num_lines
     }
 
-    let mut phase: f32 = 0.0;
-    let mut phase_vec: Vec<i16> = Vec::with_capacity(freq_vec.capacity());
+    /// Writes as much of the transmission as fits into `buffer` and returns how many samples
+    /// were written; fewer than the buffer holds means the transmission has ended.
+    pub fn fill(&mut self, buffer: &mut [f32]) -> usize {
+        let mut written = 0;
+        for (slot, sample) in buffer.iter_mut().zip(self.by_ref()) {
+            *slot = sample;
+            written += 1;
+        }
+        written
+    }
+}
 
-    const TAU: f32 = 2.0 * std::f32::consts::PI;
+impl Iterator for Encoder {
+    type Item = f32;
 
-    for freq in freq_vec {
-        phase += freq * TAU / f_samp;
-        if phase >= TAU {
-            phase -= TAU;
+    fn next(&mut self) -> Option<f32> {
+        if self.lead > 0 {
+            self.lead -= 1;
+            return Some(0.0)
         }
-        let sample = (phase.sin() * i16::MAX as f32) as i16;
-        phase_vec.push(sample);
+
+        if let Some(freq) = self.next_shaped() {
+            self.phase += freq * TAU / self.f_samp;
+            if self.phase >= TAU {
+                self.phase -= TAU;
+            }
+            return Some(self.phase.sin() * self.amplitude)
+        }
+
+        if self.trail > 0 {
+            self.trail -= 1;
+            return Some(0.0)
+        }
+        None
+    }
+}
+
+/// Generates the whole transmission at once; see `Encoder` to produce it progressively.
+pub fn encode(image_data: &RgbImage, mode: Mode, settings: &EncodeSettings) -> Result<Vec<f32>> {
+    Ok(Encoder::new(image_data, mode, settings)?.collect())
+}
+
+/// Extensions of every picture format the `image` crate was built to read.
+pub fn image_extensions() -> Vec<&'static str> {
+    image::ImageFormat::all()
+        .filter(image::ImageFormat::reading_enabled)
+        .flat_map(image::ImageFormat::extensions_str)
+        .copied()
+        .collect()
+}
+
+/// Opens a picture in any readable format, turned upright according to its EXIF orientation.
+/// Transparent areas come out black.
+pub fn load_image(path: &Path) -> Result<RgbImage> {
+    let mut decoder = image::ImageReader::open(path)?.with_guessed_format()?.into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let mut decoded = image::DynamicImage::from_decoder(decoder)?;
+    decoded.apply_orientation(orientation);
+
+    let rgba = decoded.to_rgba8();
+    Ok(RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let [r, g, b, a] = rgba.get_pixel(x, y).0;
+        let premultiply = |c: u8| ((c as u16 * a as u16 + 127) / 255) as u8;
+        Rgb([premultiply(r), premultiply(g), premultiply(b)])
+    }))
+}
+
+// Largest rectangle of the given aspect ratio centred in a `width` x `height` picture.
+fn centre_crop(width: u32, height: u32, aspect: f32) -> [u32; 4] {
+    if width as f32 / height as f32 > aspect {
+        let crop_width = u32::max(1, f32::round(height as f32 * aspect) as u32);
+        [(width - crop_width) / 2, 0, crop_width, height]
+    } else {
+        let crop_height = u32::max(1, f32::round(width as f32 / aspect) as u32);
+        [0, (height - crop_height) / 2, width, crop_height]
     }
+}
+
+/// Scales the picture to `new_width` x `new_height` the way `settings.fit` asks for.
+pub fn fit(img: &RgbImage, new_width: u32, new_height: u32, settings: &EncodeSettings) -> RgbImage {
+    let (old_width, old_height) = img.dimensions();
 
-    return phase_vec;
+    let filter = settings.filter.filter_type();
+    let aspect = new_width as f32 / new_height as f32;
 
+    let crop_and_resize = |[x, y, w, h]: [u32; 4]| {
+        let x = x.min(old_width - 1);
+        let y = y.min(old_height - 1);
+        let cropped = image::imageops::crop_imm(img, x, y, w.clamp(1, old_width - x), h.clamp(1, old_height - y)).to_image();
+        image::imageops::resize(&cropped, new_width, new_height, filter)
+    };
+
+    match settings.fit {
+        Fit::Stretch => image::imageops::resize(img, new_width, new_height, filter),
+        Fit::CentreCrop => crop_and_resize(centre_crop(old_width, old_height, aspect)),
+        Fit::Crop => crop_and_resize(settings.crop.unwrap_or_else(|| centre_crop(old_width, old_height, aspect))),
+        Fit::Letterbox => {
+            let scale = f32::min(new_width as f32 / old_width as f32, new_height as f32 / old_height as f32);
+            let scaled_width = u32::max(1, f32::round(old_width as f32 * scale) as u32);
+            let scaled_height = u32::max(1, f32::round(old_height as f32 * scale) as u32);
+            let scaled = image::imageops::resize(img, scaled_width, scaled_height, filter);
+
+            let mut canvas = RgbImage::from_pixel(new_width, new_height, settings.fill);
+            image::imageops::overlay(&mut canvas, &scaled, ((new_width - scaled_width) / 2) as i64, ((new_height - scaled_height) / 2) as i64);
+            canvas
+        }
+    }
 }
 
-pub fn resize(pixel_vec: Vec<egui::Color32>, old_width: u32, new_width: u32, old_height: u32, new_height: u32) -> Vec<egui::Color32> {
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Start of every run of at least 3 ms of 1200 Hz, found from the zero crossings of the audio.
+    fn sync_starts(samples: &[f32], f_samp: f32) -> Vec<f64> {
+        let mut crossings: Vec<f64> = Vec::new();
+        for i in 1..samples.len() {
+            let (a, b) = (samples[i-1] as f64, samples[i] as f64);
+            if (a < 0.0) != (b < 0.0) {
+                crossings.push((i - 1) as f64 + a / (a - b));
+            }
+        }
 
-    let mut img = image::RgbImage::new(old_width as u32, old_height as u32);
-    for y in 0..old_height {
-        for x in 0..old_width {
-            let idx: usize = (y * old_width + x) as usize;
-            img.put_pixel(x as u32, y as u32, image::Rgb([pixel_vec[idx][0], pixel_vec[idx][1], pixel_vec[idx][2]]));
+        let mut starts: Vec<f64> = Vec::new();
+        let mut run_start: Option<f64> = None;
+        for pair in crossings.windows(2) {
+            let f = f_samp as f64 / (2.0 * (pair[1] - pair[0]));
+            if (f - 1200.0).abs() < 60.0 {
+                run_start.get_or_insert(pair[0]);
+            } else if let Some(start) = run_start.take()
+                && (pair[0] - start) / f_samp as f64 > 0.003 {
+                starts.push(start);
+            }
         }
-    };
+        starts
+    }
+
+    #[test]
+    fn martin_line_period_matches_specification() {
+        let f_samp = 44100.0;
+        let image = RgbImage::from_pixel(320, 256, Rgb([120, 60, 200]));
+        let settings = EncodeSettings { sample_rate: f_samp as u32, ..Default::default() };
+        let audio = encode(&image, Mode::MartinM1, &settings).unwrap();
 
-    let resized_image = image::imageops::resize(&img, new_width, new_height, image::imageops::FilterType::Gaussian);
+        // Skip the leader breaks and VIS start bit, which are also 1200 Hz.
+        let starts = sync_starts(&audio, f_samp);
+        let lines = &starts[3..];
+        let period = (lines[lines.len() - 1] - lines[0]) / (lines.len() - 1) as f64 / f_samp as f64;
 
-    let new_vec: Vec<egui::Color32> = resized_image.pixels().map(|p| egui::Color32::from_rgb(p[0], p[1], p[2])).collect();
+        assert!(lines.len() > 250, "only found {} syncs", lines.len());
+        assert!((period * 1000.0 - 446.446).abs() < 0.01, "line period was {} ms", period * 1000.0);
+    }
+
+    #[test]
+    fn vis_code_reads_back() {
+        let image = RgbImage::new(320, 256);
+        for mode in [Mode::MartinM1, Mode::MartinM2] {
+            // The header is over well within a second and a half.
+            let audio: Vec<f32> = Encoder::new(&image, mode.clone(), &EncodeSettings::default()).unwrap().take(66150).collect();
+            let track = crate::demod::frequency_track(&audio, 44100, &crate::demod::PhaseDifference, &|_| {});
+            assert_eq!(crate::freq_to_img::decode_vis(&track.freqs, track.f_samp).ok(), mode.vis(), "{mode}");
+        }
+    }
+
+    #[test]
+    fn shaping_kernels_are_normalised_and_symmetric() {
+        for shaping in [Shaping::None, Shaping::RaisedCosine, Shaping::Gaussian] {
+            let kernel = shaping_kernel(&shaping, 45);
+            assert!((kernel.iter().sum::<f32>() - 1.0).abs() < 1e-5, "{shaping}");
+            assert!(kernel.iter().zip(kernel.iter().rev()).all(|(a, b)| (a - b).abs() < 1e-7), "{shaping}");
+        }
+    }
 
-    return new_vec
-}
\ No newline at end of file
+    #[test]
+    fn shaping_spreads_tone_changes_without_changing_length() {
+        let image = RgbImage::from_pixel(320, 256, Rgb([255, 0, 255]));
+        let track = |shaping: Shaping| {
+            let settings = EncodeSettings { shaping, transition_time: 1.0 / 1000.0, ..Default::default() };
+            let mut encoder = Encoder::new(&image, Mode::MartinM1, &settings).unwrap();
+            std::iter::from_fn(|| encoder.next_shaped()).take(88200).collect::<Vec<f32>>()
+        };
+        let largest_step = |track: &[f32]| track.windows(2).fold(0.0f32, |step, pair| step.max((pair[1] - pair[0]).abs()));
+
+        let hard = track(Shaping::None);
+        assert_eq!(largest_step(&hard), 800.0);
+        for shaping in [Shaping::RaisedCosine, Shaping::Gaussian] {
+            // A millisecond is 45 samples, so no step comes near the 800 Hz jump from the porch to full red.
+            let shaped = track(shaping.clone());
+            assert_eq!(shaped.len(), hard.len());
+            assert!(largest_step(&shaped) < 100.0, "{shaping} stepped {} Hz", largest_step(&shaped));
+        }
+    }
+
+    #[test]
+    fn level_sets_the_peak() {
+        let image = RgbImage::new(320, 256);
+        for level_db in [0.0, -3.0, -12.0] {
+            let settings = EncodeSettings { level_db, ..Default::default() };
+            let peak = Encoder::new(&image, Mode::MartinM1, &settings).unwrap().take(44100).fold(0.0f32, |peak, s| peak.max(s.abs()));
+            assert!((peak - 10f32.powf(level_db / 20.0)).abs() < 1e-3, "{level_db} dB gave a peak of {peak}");
+        }
+    }
+
+    #[test]
+    fn length_follows_the_sample_rate_and_silence() {
+        let image = RgbImage::new(320, 256);
+        let length = |sample_rate: u32, lead_silence: f32, trail_silence: f32| {
+            let settings = EncodeSettings { sample_rate, lead_silence, trail_silence, ..Default::default() };
+            Encoder::new(&image, Mode::MartinM2, &settings).unwrap().count()
+        };
+
+        // Each tone ends on the sample nearest its ideal time, so the lengths agree to a sample.
+        let (slow, fast) = (length(22050, 0.0, 0.0), length(48000, 0.0, 0.0));
+        assert!((slow as f64 / 22050.0 - fast as f64 / 48000.0).abs() < 1.0 / 22050.0, "{slow} and {fast} samples");
+        assert_eq!(length(22050, 0.5, 0.25), slow + 11025 + 5513);
+    }
+
+    // Red, green and blue bands across a 640 x 256 picture, the green one a 320 wide centre.
+    fn bands() -> RgbImage {
+        RgbImage::from_fn(640, 256, |x, _| match x {
+            0..160 => Rgb([255, 0, 0]),
+            160..480 => Rgb([0, 255, 0]),
+            _ => Rgb([0, 0, 255]),
+        })
+    }
+
+    fn fit_with(fit_mode: Fit, crop: Option<[u32; 4]>) -> RgbImage {
+        let settings = EncodeSettings { fit: fit_mode, crop, filter: ResizeFilter::Nearest, fill: Rgb([9, 9, 9]), ..Default::default() };
+        fit(&bands(), 320, 256, &settings)
+    }
+
+    #[test]
+    fn stretch_keeps_the_whole_picture() {
+        let fitted = fit_with(Fit::Stretch, None);
+        assert_eq!(fitted.dimensions(), (320, 256));
+        assert_eq!(fitted[(0, 128)], Rgb([255, 0, 0]));
+        assert_eq!(fitted[(160, 128)], Rgb([0, 255, 0]));
+        assert_eq!(fitted[(319, 128)], Rgb([0, 0, 255]));
+    }
+
+    #[test]
+    fn centre_crop_keeps_the_middle() {
+        let fitted = fit_with(Fit::CentreCrop, None);
+        assert_eq!(fitted.dimensions(), (320, 256));
+        assert!(fitted.pixels().all(|pixel| *pixel == Rgb([0, 255, 0])));
+    }
+
+    #[test]
+    fn letterbox_fills_the_bars() {
+        // Half size fits the width, leaving 64 lines above and below.
+        let fitted = fit_with(Fit::Letterbox, None);
+        assert_eq!(fitted.dimensions(), (320, 256));
+        assert_eq!(fitted[(160, 0)], Rgb([9, 9, 9]));
+        assert_eq!(fitted[(160, 63)], Rgb([9, 9, 9]));
+        assert_eq!(fitted[(160, 64)], Rgb([0, 255, 0]));
+        assert_eq!(fitted[(0, 128)], Rgb([255, 0, 0]));
+        assert_eq!(fitted[(160, 192)], Rgb([9, 9, 9]));
+    }
+
+    #[test]
+    fn crop_takes_the_selection() {
+        let fitted = fit_with(Fit::Crop, Some([480, 0, 160, 128]));
+        assert!(fitted.pixels().all(|pixel| *pixel == Rgb([0, 0, 255])));
+
+        // Without a selection it falls back to the centre, and one off the picture is pulled back onto it.
+        assert_eq!(fit_with(Fit::Crop, None), fit_with(Fit::CentreCrop, None));
+        let fitted = fit_with(Fit::Crop, Some([1000, 1000, 50, 50]));
+        assert_eq!(fitted.dimensions(), (320, 256));
+        assert!(fitted.pixels().all(|pixel| *pixel == Rgb([0, 0, 255])));
+    }
+
+    #[test]
+    fn martin_modes_send_one_line_short() {
+        let image = RgbImage::new(320, 256);
+        for mode in [Mode::MartinM1, Mode::MartinM2] {
+            assert_eq!(Encoder::new(&image, mode.clone(), &EncodeSettings::default()).unwrap().lines(), 255, "{mode}");
+        }
+    }
+
+    #[test]
+    fn modes_without_a_line_format_are_refused() {
+        let image = RgbImage::new(640, 496);
+        for mode in [Mode::RAW, Mode::PD120, Mode::PD180] {
+            assert!(matches!(Encoder::new(&image, mode, &EncodeSettings::default()), Err(Error::UnsupportedMode(_))));
+        }
+    }
+}
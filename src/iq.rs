@@ -0,0 +1,320 @@
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use chrono::{DateTime, Duration, Utc};
+use num_complex::{Complex, c32};
+use crate::doppler::Doppler;
+use crate::{Error, Result, audio, fft, resample};
+
+/// Rate the channel is filtered and demodulated at. Its Nyquist frequency is wide enough for
+/// NBFM with 5 kHz deviation, so reaching it filters out the neighbouring channels as well.
+pub const CHANNEL_RATE: u32 = 16000;
+
+// The block averaging that comes first only has to bring the rate down to this many times the
+// channel rate, where it still rejects anything that would alias into the channel.
+const FIRST_STAGE_MARGIN: u32 = 16;
+
+// Peak deviation of amateur NBFM, which comes out of the discriminator as full scale.
+const FM_DEVIATION: f32 = 5000.0;
+
+// Seconds between retuning to follow the Doppler shift; a low orbit pass changes it by well
+// under a hertz in that time.
+const RETUNE_INTERVAL: f64 = 0.01;
+
+/// Sample layout of a complex baseband recording.
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub enum IqFormat {
+    /// Unsigned 8 bit pairs, as written by rtl_sdr.
+    Cu8,
+    /// 32 bit float pairs, as written by GNU Radio and SDR++.
+    Cf32,
+    /// Stereo WAV with I on the left and Q on the right, as recorded by SDR#.
+    Wav,
+}
+
+impl std::fmt::Display for IqFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let label = match self {
+            IqFormat::Cu8 => "cu8 (rtl_sdr)",
+            IqFormat::Cf32 => "cf32",
+            IqFormat::Wav => "WAV (SDR#)",
+        };
+        write!(f, "{label}")
+    }
+}
+
+impl IqFormat {
+    /// Format of a raw IQ file from its extension; WAV is left out, as it usually holds audio.
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "cu8" => Some(IqFormat::Cu8),
+            "cf32" | "fc32" | "cfile" => Some(IqFormat::Cf32),
+            _ => None,
+        }
+    }
+}
+
+/// Extensions of the raw IQ formats, for file pickers.
+pub fn iq_extensions() -> Vec<&'static str> {
+    vec!["cu8", "cf32", "fc32", "cfile"]
+}
+
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub enum IqDemod {
+    Nbfm,
+    Usb,
+}
+
+impl std::fmt::Display for IqDemod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let label = match self {
+            IqDemod::Nbfm => "NBFM",
+            IqDemod::Usb => "USB",
+        };
+        write!(f, "{label}")
+    }
+}
+
+#[derive(Clone)]
+pub struct IqSettings {
+    pub format: IqFormat,
+    /// Sample rate of raw files, which do not record it; WAV files carry their own.
+    pub sample_rate: u32,
+    /// Where the signal sits relative to the centre of the recording, in Hz. For USB this is
+    /// the suppressed carrier, i.e. the dial frequency.
+    pub offset: f32,
+    pub demod: IqDemod,
+    /// Follows a satellite's Doppler shift on top of the offset.
+    pub doppler: Option<Doppler>,
+}
+
+impl Default for IqSettings {
+    fn default() -> Self {
+        Self {
+            format: IqFormat::Cu8,
+            sample_rate: 2_048_000,
+            offset: 0.0,
+            demod: IqDemod::Nbfm,
+            doppler: None,
+        }
+    }
+}
+
+// Moves the signal down to 0 Hz and averages blocks of `factor` samples. The signal sits at
+// `tuning[n]` for the nth stretch of `interval` samples, and at the last entry after that.
+struct Downconverter {
+    oscillator: Complex<f32>,
+    step: Complex<f32>,
+    tuning: Vec<f64>,
+    interval: usize,
+    sample_rate: u32,
+    pushed: usize,
+    factor: usize,
+    sum: Complex<f32>,
+    count: usize,
+    output: Vec<Complex<f32>>,
+}
+
+impl Downconverter {
+    fn new(tuning: Vec<f64>, interval: usize, sample_rate: u32, factor: usize) -> Self {
+        Self {
+            oscillator: c32(1.0, 0.0),
+            step: c32(1.0, 0.0),
+            tuning,
+            interval: interval.max(1),
+            sample_rate,
+            pushed: 0,
+            factor,
+            sum: c32(0.0, 0.0),
+            count: 0,
+            output: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, sample: Complex<f32>) {
+        if self.pushed.is_multiple_of(self.interval) {
+            let stretch = usize::min(self.pushed / self.interval, self.tuning.len() - 1);
+            let turn = -std::f64::consts::TAU * self.tuning[stretch] / self.sample_rate as f64;
+            self.step = c32(turn.cos() as f32, turn.sin() as f32);
+        }
+        self.pushed += 1;
+        self.sum += sample * self.oscillator;
+        self.oscillator *= self.step;
+        self.count += 1;
+        if self.count == self.factor {
+            self.output.push(self.sum / self.factor as f32);
+            self.sum = c32(0.0, 0.0);
+            self.count = 0;
+            // Rounding makes the oscillator's amplitude creep, so pull it back onto the unit circle.
+            self.oscillator /= self.oscillator.norm();
+        }
+    }
+}
+
+// Largest factor that divides `sample_rate` exactly and leaves at least the first stage margin.
+fn first_stage_factor(sample_rate: u32) -> usize {
+    let most = u32::max(1, sample_rate / (FIRST_STAGE_MARGIN * CHANNEL_RATE));
+    (1..=most).rev().find(|factor| sample_rate.is_multiple_of(*factor)).unwrap_or(1) as usize
+}
+
+// Reads frames of `frame_len` bytes that start with an I and a Q sample of `width` bytes each,
+// in blocks, so a long capture is never held in memory whole.
+fn for_each_pair(mut reader: impl Read, width: usize, frame_len: usize, sample: fn(&[u8]) -> f32, mut pair: impl FnMut(Complex<f32>)) -> Result<()> {
+    let mut block = vec![0u8; 1 << 16];
+    let mut filled = 0;
+    loop {
+        let read = reader.read(&mut block[filled..])?;
+        if read == 0 {
+            return Ok(())
+        }
+        filled += read;
+        let whole = filled - filled % frame_len;
+        for bytes in block[..whole].chunks_exact(frame_len) {
+            pair(c32(sample(&bytes[..width]), sample(&bytes[width..2 * width])));
+        }
+        block.copy_within(whole..filled, 0);
+        filled -= whole;
+    }
+}
+
+// Where the signal sits every `RETUNE_INTERVAL` over the recording. Without a start time the
+// recording is taken to have ended when the file was last written.
+fn tuning(path: &Path, settings: &IqSettings, duration: f64) -> Result<Vec<f64>> {
+    let Some(doppler) = &settings.doppler else {
+        return Ok(vec![settings.offset as f64])
+    };
+    let start = match doppler.start {
+        Some(start) => start,
+        None => {
+            let end: DateTime<Utc> = std::fs::metadata(path)?.modified()?.into();
+            end - Duration::microseconds((duration * 1e6) as i64)
+        }
+    };
+    let curve = doppler.curve(start, duration, RETUNE_INTERVAL)?;
+    Ok(curve.into_iter().map(|shift| settings.offset as f64 + shift).collect())
+}
+
+/// Reads an IQ recording and demodulates the signal at `settings.offset`, following the
+/// Doppler shift when one is given, into audio at `CHANNEL_RATE`, ready for the same chain
+/// as a sound card recording.
+pub fn read_iq(path: &Path, settings: &IqSettings) -> Result<(Vec<f32>, u32)> {
+    let (baseband, sample_rate) = match settings.format {
+        IqFormat::Cu8 | IqFormat::Cf32 => {
+            let sample_rate = settings.sample_rate;
+            if sample_rate == 0 {
+                return Err(Error::UnsupportedAudio(String::from("IQ sample rate must be set")))
+            }
+            let width = if settings.format == IqFormat::Cu8 { 1 } else { 4 };
+            let pairs = std::fs::metadata(path)?.len() / (2 * width as u64);
+            let tuning = tuning(path, settings, pairs as f64 / sample_rate as f64)?;
+            let interval = (sample_rate as f64 * RETUNE_INTERVAL) as usize;
+            let mut converter = Downconverter::new(tuning, interval, sample_rate, first_stage_factor(sample_rate));
+            let reader = BufReader::new(File::open(path)?);
+            if settings.format == IqFormat::Cu8 {
+                for_each_pair(reader, width, 2 * width, |b| (b[0] as f32 - 127.5) / 127.5, |z| converter.push(z))?;
+            } else {
+                for_each_pair(reader, width, 2 * width, |b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]), |z| converter.push(z))?;
+            }
+            (converter.output, sample_rate)
+        }
+        IqFormat::Wav => {
+            let (wav, reader) = audio::open_wav(path)?;
+            if wav.channels != 2 {
+                return Err(Error::UnsupportedWav(String::from("IQ recordings need two channels")))
+            }
+
+            let sample_rate = wav.sample_rate;
+            let tuning = tuning(path, settings, wav.frames as f64 / sample_rate as f64)?;
+            let interval = (sample_rate as f64 * RETUNE_INTERVAL) as usize;
+            let mut converter = Downconverter::new(tuning, interval, sample_rate, first_stage_factor(sample_rate));
+            for_each_pair(reader, wav.width, wav.frame_len, wav.sample, |z| converter.push(z))?;
+            (converter.output, sample_rate)
+        }
+    };
+
+    // The resampler's low pass at the channel rate is the channel filter.
+    let first_rate = sample_rate / first_stage_factor(sample_rate) as u32;
+    let i: Vec<f32> = baseband.iter().map(|z| z.re).collect();
+    let q: Vec<f32> = baseband.iter().map(|z| z.im).collect();
+    let channel: Vec<Complex<f32>> = resample::resample(&i, first_rate, CHANNEL_RATE).into_iter()
+        .zip(resample::resample(&q, first_rate, CHANNEL_RATE))
+        .map(|(i, q)| c32(i, q))
+        .collect();
+
+    let audio = match settings.demod {
+        IqDemod::Nbfm => demodulate_fm(&channel, CHANNEL_RATE as f32),
+        IqDemod::Usb => demodulate_usb(channel, CHANNEL_RATE as f32),
+    };
+    Ok((audio, CHANNEL_RATE))
+}
+
+/// Quadrature discriminator: the phase step between samples, scaled so full deviation is ±1.0.
+pub fn demodulate_fm(channel: &[Complex<f32>], f_samp: f32) -> Vec<f32> {
+    channel.windows(2)
+        .map(|pair| (pair[1] * pair[0].conj()).arg() * f_samp / std::f32::consts::TAU / FM_DEVIATION)
+        .collect()
+}
+
+/// Keeps 200 Hz to 3 kHz above the carrier and drops everything below it.
+pub fn demodulate_usb(channel: Vec<Complex<f32>>, f_samp: f32) -> Vec<f32> {
+    let len = channel.len();
+    let mut audio: Vec<f32> = fft::hilbert(channel, f_samp, 200.0, 3000.0).iter().map(|z| z.re).collect();
+    // The transform pads to a power of two.
+    audio.truncate(len);
+    audio
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn discriminator_recovers_deviation() {
+        // A carrier held 2.5 kHz above the channel centre reads as half of full deviation.
+        let step = std::f32::consts::TAU * 2500.0 / CHANNEL_RATE as f32;
+        let channel: Vec<Complex<f32>> = (0..1000).map(|n| Complex::from_polar(1.0, step * n as f32)).collect();
+        let audio = demodulate_fm(&channel, CHANNEL_RATE as f32);
+        assert_eq!(audio.len(), 999);
+        assert!(audio.iter().all(|s| (s - 0.5).abs() < 1e-3));
+    }
+
+    #[test]
+    fn wav_recording_is_tuned_and_demodulated() {
+        // A carrier 20 kHz above the centre, swung ±2.5 kHz by a 1 kHz tone.
+        let f_samp = 96000.0;
+        let path = std::env::temp_dir().join(format!("sstv-iq-{}.wav", std::process::id()));
+        let spec = hound::WavSpec { channels: 2, sample_rate: f_samp as u32, bits_per_sample: 16, sample_format: hound::SampleFormat::Int };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for n in 0..96000 {
+            let t = n as f64 / f_samp;
+            let phase = std::f64::consts::TAU * 20000.0 * t - 2500.0 / 1000.0 * (std::f64::consts::TAU * 1000.0 * t).cos();
+            writer.write_sample((phase.cos() * 16000.0) as i16).unwrap();
+            writer.write_sample((phase.sin() * 16000.0) as i16).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let settings = IqSettings { format: IqFormat::Wav, offset: 20000.0, ..Default::default() };
+        let result = read_iq(&path, &settings);
+        std::fs::remove_file(&path).unwrap();
+        let (audio, sample_rate) = result.unwrap();
+        assert_eq!(sample_rate, CHANNEL_RATE);
+
+        // Away from the edges, where the filters run off the recording, count rising zero crossings.
+        let middle = &audio[1600..14400];
+        let crossings = middle.windows(2).filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0).count();
+        let frequency = crossings as f32 * CHANNEL_RATE as f32 / middle.len() as f32;
+        assert!((frequency - 1000.0).abs() < 5.0, "{frequency} Hz");
+
+        let peak = middle.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        assert!((peak - 0.5).abs() < 0.05, "peak {peak}");
+    }
+
+    #[test]
+    fn first_stage_leaves_room_for_the_channel() {
+        assert_eq!(first_stage_factor(2_048_000), 8);
+        assert_eq!(first_stage_factor(250_000), 1);
+        assert_eq!(first_stage_factor(2_400_000), 8);
+    }
+}
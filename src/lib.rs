@@ -0,0 +1,104 @@
+//! SSTV encoding and decoding over `image::RgbImage` and plain sample buffers.
+//!
+//! The window is one consumer of this crate; the conversions it needs to and from egui
+//! types live in `gui`, behind the opt-in `gui` feature. The command line front end is
+//! part of the binaries rather than the library, so nothing here prints.
+
+pub mod audio;
+pub mod demod;
+pub mod doppler;
+pub mod error;
+pub mod fft;
+pub mod filter;
+pub mod freq_to_img;
+#[cfg(feature = "gui")]
+pub mod gui;
+pub mod img_to_freq;
+pub mod iq;
+pub mod overlay;
+pub mod resample;
+pub mod save;
+pub mod waterfall;
+
+pub use error::{Error, Result};
+
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub enum Mode {
+    RAW,
+    MartinM1,
+    MartinM2,
+    //Scottie1,
+    PD120,
+    PD180,
+}
+
+impl std::fmt::Display for Mode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let label = match self {
+            Mode::RAW => "Raw / BW",
+            Mode::MartinM1 => "Martin M1",
+            Mode::MartinM2 => "Martin M2",
+            //Mode::Scottie1 => "Scottie 1",
+            Mode::PD120 => "PD 120",
+            Mode::PD180 => "PD 180",
+        };
+        write!(f, "{label}")
+    }
+}
+
+impl Mode {
+    /// Duration of a single pixel (one colour component) in seconds.
+    pub fn pixel_time(&self) -> f32 {
+        match self {
+            Mode::RAW => 0.19 / 1000.0,
+            Mode::MartinM1 => 0.4576 / 1000.0,
+            Mode::MartinM2 => 0.2288 / 1000.0,
+            Mode::PD120 => 0.19 / 1000.0,
+            Mode::PD180 => 0.286 / 1000.0,
+        }
+    }
+
+    /// Duration of the line sync pulse in seconds.
+    pub fn sync_time(&self) -> f32 {
+        match self {
+            Mode::RAW | Mode::MartinM1 | Mode::MartinM2 => 4.862 / 1000.0,
+            Mode::PD120 | Mode::PD180 => 20.0 / 1000.0,
+        }
+    }
+
+    /// Picture width and number of lines sent.
+    pub fn resolution(&self) -> (usize, usize) {
+        match self {
+            Mode::RAW => (640, 256),
+            Mode::MartinM1 | Mode::MartinM2 => (320, 256),
+            Mode::PD120 | Mode::PD180 => (640, 496),
+        }
+    }
+
+    /// VIS code sent before the picture, if the mode has one.
+    pub fn vis(&self) -> Option<u8> {
+        match self {
+            Mode::RAW => None,
+            Mode::MartinM1 => Some(44),
+            Mode::MartinM2 => Some(40),
+            Mode::PD120 => Some(95),
+            Mode::PD180 => Some(96),
+        }
+    }
+
+    pub fn from_vis(vis: u8) -> Option<Mode> {
+        [Mode::MartinM1, Mode::MartinM2, Mode::PD120, Mode::PD180].into_iter().find(|mode| mode.vis() == Some(vis))
+    }
+
+    /// Time from one sync pulse to the next in seconds, if the mode defines it.
+    pub fn line_time(&self) -> Option<f32> {
+        match self {
+            Mode::RAW => None,
+            Mode::MartinM1 => Some(446.446 / 1000.0),
+            Mode::MartinM2 => Some(226.798 / 1000.0),
+            Mode::PD120 => Some(508.48 / 1000.0),
+            Mode::PD180 => Some(754.24 / 1000.0),
+        }
+    }
+}
@@ -1,94 +1,311 @@
 use eframe::egui;
-use egui::{ColorImage, RichText, TextureHandle, Visuals};
+use egui::{RichText, TextureHandle, Visuals};
+use image::RgbImage;
 use rfd::{self, FileDialog};
-use num_complex::{Complex, c32};
+use sstv::{Mode, audio, demod, doppler, filter, freq_to_img, gui, img_to_freq, iq, overlay, save, waterfall};
+use demod::DemodMethod;
+use doppler::{Doppler, Observer, Satellite};
+use filter::PostFilter;
+use img_to_freq::{Fit, ResizeFilter, Shaping};
+use audio::{BitDepth, Channels, InputChannel};
+use iq::{IqDemod, IqFormat, IqSettings};
+use overlay::{OverlayFields, OverlayTemplate, TextLayer};
+use save::{AutoSave, DecodeStats, ImageFormat, ImageInfo};
+use waterfall::Waterfall;
+use std::path::{Path, PathBuf};
 use std::thread;
 use std::sync::{Arc, Mutex};
-use image::{ImageBuffer, Rgba};
 
-pub mod fft;
-pub mod freq_to_img;
-pub mod img_to_freq;
+mod cli;
 
 struct Globals {
     show_decode_panel: bool,
     decode_load_path: Option<String>,
+    input_channel: InputChannel,
+    iq_enabled: bool,
+    iq_settings: IqSettings,
+    doppler_enabled: bool,
+    doppler_satellite: Option<Satellite>,
+    doppler_observer: Option<Observer>,
+    doppler_downlink: f64,
+    doppler_start: String,
     encode_load_path: Option<String>,
     frequency_mutex: Arc<Mutex<Vec<f32>>>,
-    main_image: Option<ColorImage>,
-    encode_image: Option<ColorImage>,
-    sound_buffer: Option<Vec<i16>>,
+    sample_rate_mutex: Arc<Mutex<f32>>,
+    snr_mutex: Arc<Mutex<Option<f32>>>,
+    main_image: Option<RgbImage>,
+    sync_confidence: Vec<f32>,
+    freq_offset: Option<f32>,
+    image_info: Option<ImageInfo>,
+    save_format: ImageFormat,
+    auto_save: AutoSave,
+    encode_image: Option<RgbImage>,
+    sound_buffer: Option<Vec<f32>>,
+    sound_rate: u32,
     main_texture_handle: Option<TextureHandle>,
+    waterfall_mutex: Arc<Mutex<Option<Waterfall>>>,
+    waterfall: Option<Waterfall>,
+    waterfall_texture: Option<TextureHandle>,
+    segment: Option<[f32; 2]>,
+    segment_drag_start: Option<f32>,
     pending_image_decode: Arc<Mutex<bool>>,
     decode_mode: Mode,
     encode_mode: Mode,
+    encode_settings: img_to_freq::EncodeSettings,
+    crop_drag_start: Option<egui::Pos2>,
+    overlay_enabled: bool,
+    overlay_template: OverlayTemplate,
+    overlay_fields: OverlayFields,
+    demod_method: DemodMethod,
+    decode_settings: freq_to_img::DecodeSettings,
     is_decoding: bool,
+    decode_thread: Option<thread::JoinHandle<()>>,
     program_status: Arc<Mutex<String>>
 }
 
-#[derive(Clone)]
-#[derive(PartialEq)]
-pub enum Mode {
-    RAW,
-    MartinM1,
-    MartinM2,
-    //Scottie1,
-    PD120,
-    PD180,
-}
-
-impl std::fmt::Display for Mode {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let label = match self {
-            Mode::RAW => "Raw / BW",
-            Mode::MartinM1 => "Martin M1",
-            Mode::MartinM2 => "Martin M2",
-            //Mode::Scottie1 => "Scottie 1",
-            Mode::PD120 => "PD 120",
-            Mode::PD180 => "PD 180",
-        };
-        write!(f, "{label}")
-    }
-}
-
 impl Default for Globals {
     fn default() -> Self {
 
         Self { 
             show_decode_panel: true,
             decode_load_path: None,
+            input_channel: InputChannel::Sum,
+            iq_enabled: false,
+            iq_settings: IqSettings::default(),
+            doppler_enabled: false,
+            doppler_satellite: None,
+            doppler_observer: None,
+            doppler_downlink: doppler::ISS_DOWNLINK,
+            doppler_start: String::new(),
             encode_load_path: None,
             frequency_mutex: Arc::new(Mutex::new(vec![0.0])),
+            sample_rate_mutex: Arc::new(Mutex::new(44100.0)),
+            snr_mutex: Arc::new(Mutex::new(None)),
             main_image: None,
+            sync_confidence: Vec::new(),
+            freq_offset: None,
+            image_info: None,
+            save_format: ImageFormat::Png,
+            auto_save: AutoSave::default(),
             encode_image: None,
             sound_buffer: None,
+            sound_rate: 44100,
             main_texture_handle: None,
+            waterfall_mutex: Arc::new(Mutex::new(None)),
+            waterfall: None,
+            waterfall_texture: None,
+            segment: None,
+            segment_drag_start: None,
             pending_image_decode: Arc::new(Mutex::new(false)),
             decode_mode: Mode::RAW,
             encode_mode: Mode::MartinM1,
+            encode_settings: img_to_freq::EncodeSettings::default(),
+            crop_drag_start: None,
+            overlay_enabled: false,
+            overlay_template: OverlayTemplate::default(),
+            overlay_fields: OverlayFields::default(),
+            demod_method: DemodMethod::PhaseDifference,
+            decode_settings: freq_to_img::DecodeSettings::default(),
             is_decoding: false,
+            decode_thread: None,
             program_status: Arc::new(Mutex::new(String::from("Waiting...")))
         }
     }
 }
 
+impl Globals {
+    // Raw IQ files can only be IQ, so picking one switches the IQ stage on with its format.
+    fn set_decode_path(&mut self, path: &Path) {
+        let extension = path.extension().map(|ext| ext.to_string_lossy().to_string()).unwrap_or_default();
+        if let Some(format) = IqFormat::from_extension(&extension) {
+            self.iq_enabled = true;
+            self.iq_settings.format = format;
+        }
+        self.decode_load_path = Some(path.display().to_string());
+    }
+
+    // Without a start time, the recording is taken to have ended when its file was written.
+    fn doppler(&self) -> sstv::Result<Option<Doppler>> {
+        if !self.doppler_enabled {
+            return Ok(None)
+        }
+        let (Some(satellite), Some(observer)) = (&self.doppler_satellite, &self.doppler_observer) else {
+            return Err(sstv::Error::Orbit(String::from("choose a TLE and an observer file")))
+        };
+        let start = match self.doppler_start.trim() {
+            "" => None,
+            start => Some(chrono::DateTime::parse_from_rfc3339(start).map_err(|e| sstv::Error::Orbit(format!("start time {start}: {e}")))?.to_utc()),
+        };
+        Ok(Some(Doppler {
+            satellite: satellite.clone(),
+            observer: observer.clone(),
+            downlink: self.doppler_downlink,
+            start,
+        }))
+    }
+
+    fn build_image(&mut self) -> sstv::Result<()> {
+        let f_samp = *self.sample_rate_mutex.lock().unwrap();
+        let mut freqs = self.frequency_mutex.lock().unwrap().clone();
+        if let Some([start, end]) = self.segment {
+            freqs.truncate((end * f_samp) as usize);
+            freqs.drain(..usize::min((start * f_samp) as usize, freqs.len()));
+        }
+        let decoded = freq_to_img::decode_image(freqs, f_samp, self.decode_mode.clone(), &self.decode_settings)?;
+        let source = self.decode_load_path.as_ref().map(Path::new);
+        self.image_info = Some(ImageInfo::new(&self.decode_mode, &decoded, source, *self.snr_mutex.lock().unwrap()));
+        self.main_image = Some(decoded.image);
+        self.sync_confidence = decoded.sync_confidence;
+        self.freq_offset = decoded.freq_offset;
+        Ok(())
+    }
+
+    fn recalculate_image(&mut self) {
+        if let Err(e) = self.build_image() {
+            *self.program_status.lock().unwrap() = format!("Decode Failed: {e}");
+        }
+        self.main_texture_handle = None;
+    }
+}
+
+impl Globals {
+    /// Writes the decoded picture into the auto save folder, with its thumbnail and statistics.
+    fn save_decode(&mut self) {
+        let Some(image_data) = &self.main_image else { return };
+
+        let info = self.image_info.clone().unwrap_or_default();
+        let stats = DecodeStats::new(info, image_data, &self.sync_confidence, &self.demod_method, &self.decode_settings);
+
+        let status = match self.auto_save.save(image_data, &stats) {
+            Ok(path) => format!("Saved {}", path.file_name().unwrap_or_default().to_string_lossy()),
+            Err(e) => format!("Auto Save Failed: {e}"),
+        };
+        *self.program_status.lock().unwrap() = status;
+    }
+
+    /// Builds a reply around the decoded picture and switches to the encode panel with it loaded.
+    fn start_reply(&mut self) {
+        let Some(received) = &self.main_image else { return };
+
+        // Reply in the mode we heard, as long as the encoder can send it.
+        let mode = match self.decode_mode {
+            Mode::MartinM1 | Mode::MartinM2 => self.decode_mode.clone(),
+            Mode::RAW | Mode::PD120 | Mode::PD180 => self.encode_mode.clone(),
+        };
+
+        match overlay::reply(received, &mode, &self.encode_settings, &self.overlay_template) {
+            Ok(composite) => {
+                if mode != self.decode_mode {
+                    *self.program_status.lock().unwrap() = format!("Cannot send {}, replying in {}", self.decode_mode, mode);
+                }
+                self.encode_image = Some(composite);
+                self.encode_load_path = Some(String::from("Reply"));
+                self.encode_settings.crop = None;
+                self.encode_mode = mode;
+                self.overlay_enabled = true;
+                self.show_decode_panel = false;
+                self.preview_overlay();
+            }
+            Err(e) => {
+                *self.program_status.lock().unwrap() = format!("Reply Failed: {e}");
+            }
+        }
+    }
+
+    fn load_encode_image(&mut self, path: PathBuf) {
+        match img_to_freq::load_image(&path) {
+            Ok(new_image) => {
+                self.encode_load_path = Some(path.display().to_string());
+                self.encode_image = Some(new_image);
+                self.encode_settings.crop = None;
+                self.main_image = self.encode_image.clone();
+                self.main_texture_handle = None;
+            }
+            Err(e) => {
+                *self.program_status.lock().unwrap() = format!("Load Failed: {e}");
+            }
+        }
+    }
+
+    /// Shows the encode picture with the overlay drawn on it, as it will be sent.
+    fn preview_overlay(&mut self) {
+        let Some(image_data) = &self.encode_image else { return };
+        match overlay::compose(image_data, &self.encode_mode, &self.encode_settings, &self.overlay_template, &self.overlay_fields) {
+            Ok(composite) => {
+                self.main_image = Some(composite);
+                self.main_texture_handle = None;
+            }
+            Err(e) => {
+                *self.program_status.lock().unwrap() = format!("Overlay Failed: {e}");
+            }
+        }
+    }
+
+    /// Keeps freshly encoded audio for playback and saving, or reports why there is none.
+    fn set_sound(&mut self, encoded: sstv::Result<Vec<f32>>) {
+        match encoded {
+            Ok(samples) => {
+                self.sound_buffer = Some(samples);
+                self.sound_rate = self.encode_settings.sample_rate;
+            }
+            Err(e) => {
+                *self.program_status.lock().unwrap() = format!("Encode Failed: {e}");
+            }
+        }
+    }
+}
+
 impl eframe::App for Globals {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         ctx.set_visuals(Visuals::dark());
 
+        // A decode thread that stopped without handing over a track would otherwise leave its last status up.
+        if let Some(handle) = self.decode_thread.take_if(|handle| handle.is_finished()) {
+            self.is_decoding = false;
+            if handle.join().is_err() {
+                *self.program_status.lock().unwrap() = String::from("Decode Failed: the decoder stopped unexpectedly");
+            }
+        }
+
         if *self.pending_image_decode.lock().unwrap() {
             *self.pending_image_decode.lock().unwrap() = false;
-            self.main_image = Some(freq_to_img::decode_image(self.frequency_mutex.lock().unwrap().clone(), self.decode_mode.clone()));
-            self.main_texture_handle = None;
-            *self.program_status.lock().unwrap() = String::from("Done!");
+            match self.build_image() {
+                Ok(()) => {
+                    self.main_texture_handle = None;
+                    *self.program_status.lock().unwrap() = String::from("Done!");
+                    if self.auto_save.enabled {
+                        self.save_decode();
+                    }
+                }
+                Err(e) => {
+                    *self.program_status.lock().unwrap() = format!("Decode Failed: {e}");
+                }
+            }
+        }
+
+        if let Some(waterfall) = self.waterfall_mutex.lock().unwrap().take() {
+            self.waterfall_texture = Some(ctx.load_texture("waterfall", gui::to_color_image(&waterfall.to_image()), egui::TextureOptions::LINEAR));
+            self.waterfall = Some(waterfall);
         }
 
-        if let Some(image_data) = &self.main_image {
-            if self.main_texture_handle.is_none() {
-                self.main_texture_handle = Some(ctx.load_texture("main_image", image_data.clone(), egui::TextureOptions::NEAREST))
+        // Recordings go to the decoder, anything else is treated as a picture to send.
+        let dropped = ctx.input(|i| i.raw.dropped_files.iter().find_map(|file| file.path.clone()));
+        if let Some(path) = dropped {
+            let extension = path.extension().map(|ext| ext.to_string_lossy().to_ascii_lowercase()).unwrap_or_default();
+            if audio::audio_extensions().contains(&extension.as_str()) || iq::iq_extensions().contains(&extension.as_str()) {
+                self.set_decode_path(&path);
+                self.show_decode_panel = true;
+            } else {
+                self.show_decode_panel = false;
+                self.load_encode_image(path);
             }
         }
 
+        if let Some(image_data) = &self.main_image
+            && self.main_texture_handle.is_none() {
+            self.main_texture_handle = Some(ctx.load_texture("main_image", gui::to_color_image(image_data), egui::TextureOptions::NEAREST))
+        }
+
         egui::TopBottomPanel::top("top_menu").frame(egui::Frame::new().fill(egui::Color32::from_rgb(20, 20, 21)).inner_margin(10.0)).show(ctx, |ui|{
             ui.horizontal(|ui| {
                 ui.heading(RichText::new("73-SSTV").size(24.0));
@@ -109,9 +326,84 @@ impl eframe::App for Globals {
                 ui.separator();
                 ui.heading(RichText::new("Input").size(32.0));
 
-                if ui.add_sized(egui::vec2(120.0, 30.0), egui::Button::new(RichText::new("Select File 📁"))).clicked() {
-                    if let Some(file_path) = FileDialog::new().add_filter(".wav File", &["wav"]).pick_file() {
-                        self.decode_load_path = Some(file_path.display().to_string());
+                if ui.add_sized(egui::vec2(120.0, 30.0), egui::Button::new(RichText::new("Select File 📁"))).clicked()
+                    && let Some(file_path) = FileDialog::new().add_filter("Recording", &audio::audio_extensions()).add_filter("IQ Recording", &iq::iq_extensions()).pick_file() {
+                    self.set_decode_path(&file_path);
+                }
+
+                egui::ComboBox::from_label("Input Channel").selected_text(self.input_channel.to_string()).show_ui(ui, |ui| {
+                    for option in [
+                        InputChannel::Sum,
+                        InputChannel::Left,
+                        InputChannel::Right,
+                        ] {
+                        if ui.selectable_value(&mut self.input_channel, option.clone(), option.to_string()).clicked() {
+                            self.input_channel = option;
+                        }
+                    }
+                });
+
+                ui.checkbox(&mut self.iq_enabled, "SDR IQ Input");
+
+                if self.iq_enabled {
+                    egui::ComboBox::from_label("IQ Format").selected_text(self.iq_settings.format.to_string()).show_ui(ui, |ui| {
+                        for option in [
+                            IqFormat::Cu8,
+                            IqFormat::Cf32,
+                            IqFormat::Wav,
+                            ] {
+                            if ui.selectable_value(&mut self.iq_settings.format, option.clone(), option.to_string()).clicked() {
+                                self.iq_settings.format = option;
+                            }
+                        }
+                    });
+                    if self.iq_settings.format != IqFormat::Wav {
+                        ui.add(egui::DragValue::new(&mut self.iq_settings.sample_rate).range(16000..=20_000_000).speed(1000.0).prefix("Sample Rate: ").suffix(" Hz"));
+                    }
+                    ui.add(egui::DragValue::new(&mut self.iq_settings.offset).range(-10_000_000.0..=10_000_000.0).speed(100.0).prefix("Offset: ").suffix(" Hz"));
+                    egui::ComboBox::from_label("IQ Demodulation").selected_text(self.iq_settings.demod.to_string()).show_ui(ui, |ui| {
+                        for option in [
+                            IqDemod::Nbfm,
+                            IqDemod::Usb,
+                            ] {
+                            if ui.selectable_value(&mut self.iq_settings.demod, option.clone(), option.to_string()).clicked() {
+                                self.iq_settings.demod = option;
+                            }
+                        }
+                    });
+
+                    ui.checkbox(&mut self.doppler_enabled, "Doppler Correction");
+
+                    if self.doppler_enabled {
+                        ui.horizontal(|ui| {
+                            if ui.button("TLE 📁").clicked()
+                                && let Some(path) = FileDialog::new().add_filter("TLE", &["tle", "txt"]).pick_file() {
+                                match Satellite::read_tle(&path) {
+                                    Ok(satellite) => self.doppler_satellite = Some(satellite),
+                                    Err(e) => *self.program_status.lock().unwrap() = format!("Load Failed: {e}"),
+                                }
+                            }
+                            if ui.button("Observer 📁").clicked()
+                                && let Some(path) = FileDialog::new().add_filter("Observer", &["json"]).pick_file() {
+                                match Observer::load(&path) {
+                                    Ok(observer) => self.doppler_observer = Some(observer),
+                                    Err(e) => *self.program_status.lock().unwrap() = format!("Load Failed: {e}"),
+                                }
+                            }
+                        });
+                        match &self.doppler_satellite {
+                            Some(satellite) => ui.label(format!("Satellite: {} (epoch {})", satellite.name, satellite.epoch.format("%Y-%m-%d"))),
+                            None => ui.label("Satellite: none chosen"),
+                        };
+                        match &self.doppler_observer {
+                            Some(observer) => ui.label(format!("Observer: {:.3}, {:.3}", observer.latitude, observer.longitude)),
+                            None => ui.label("Observer: none chosen"),
+                        };
+                        ui.add(egui::DragValue::new(&mut self.doppler_downlink).range(1e6..=10e9).speed(1000.0).prefix("Downlink: ").suffix(" Hz"));
+                        ui.horizontal(|ui| {
+                            ui.add(egui::TextEdit::singleline(&mut self.doppler_start).hint_text("file time").desired_width(220.0));
+                            ui.label("Start (RFC 3339)");
+                        });
                     }
                 }
 
@@ -122,54 +414,58 @@ impl eframe::App for Globals {
                         
                         let file_path_clone = file_path.clone();
                         let freq_buffer = self.frequency_mutex.clone();
+                        let sample_rate = self.sample_rate_mutex.clone();
+                        let snr_buffer = self.snr_mutex.clone();
                         let pending_decode = self.pending_image_decode.clone();
                         let status = self.program_status.clone();
-                        thread::spawn(move || {
+                        let demodulator = self.demod_method.demodulator();
+                        let input_channel = self.input_channel.clone();
+                        let iq_settings = self.iq_enabled.then(|| self.iq_settings.clone());
+                        let doppler = self.doppler();
+                        let waterfall_buffer = self.waterfall_mutex.clone();
+                        // The last recording's waterfall must not outlive it, or a drag on it would
+                        // cut the old track if this file cannot be read.
+                        *waterfall_buffer.lock().unwrap() = None;
+                        self.waterfall = None;
+                        self.waterfall_texture = None;
+                        self.segment = None;
+                        self.segment_drag_start = None;
+                        self.is_decoding = true;
+                        self.decode_thread = Some(thread::spawn(move || {
                             let set_status = |new_text: &str| {
                                 *status.lock().unwrap() = new_text.to_string();
                             };
 
                             set_status("Reading File...");
 
-                            let mut file_reader = hound::WavReader::open(file_path_clone).expect("Error Opening File");
-                            let file_specs = file_reader.spec();
-                            println!("{:?}", file_specs);
-
-                            let mut samples: Vec<Complex<f32>>;
-
-                            if file_specs.sample_format == hound::SampleFormat::Float {
-                                samples = file_reader.samples::<f32>()
-                                .map(|z| c32(z.unwrap() / (i32::MAX as f32),0.0))
-                                .collect();
-                            } else {
-                                samples = file_reader.samples::<i32>()
-                                .map(|z| c32(z.unwrap() as f32 / (i32::MAX as f32),0.0))
-                                .collect();
-                            }
-                            if file_specs.channels == 2 {
-                                samples = samples.chunks(2)
-                                .map(|pair| 0.5 * (pair[0] + pair[1]))
-                                .collect()
-                            }
-
-                            set_status("Performing Hilbert Transform...");
-                                            
-                            let iq_samples = fft::hilbert(samples, file_specs.sample_rate as f32, 900.0, 2500.0);
+                            let read = match &iq_settings {
+                                Some(iq_settings) => doppler.and_then(|doppler| {
+                                    set_status("Demodulating IQ...");
+                                    iq::read_iq(Path::new(&file_path_clone), &IqSettings { doppler, ..iq_settings.clone() })
+                                }),
+                                None => audio::read_audio(Path::new(&file_path_clone), &input_channel),
+                            };
+                            let (samples, file_rate) = match read {
+                                Ok(read) => read,
+                                Err(e) => {
+                                    set_status(&format!("Read Failed: {e}"));
+                                    return
+                                }
+                            };
 
-                            set_status("Calculating Phase...");
+                            set_status("Building Waterfall...");
+                            *waterfall_buffer.lock().unwrap() = Some(Waterfall::new(&samples, file_rate));
 
-                            let mut freqs: Vec<f32> = Vec::with_capacity(iq_samples.len()-1);
+                            let track = demod::frequency_track(&samples, file_rate, demodulator.as_ref(), &set_status);
 
-                            for i in 1..iq_samples.len() {
-                                let diff = Complex::arg(iq_samples[i] * Complex::conj(&iq_samples[i-1]));
-                                freqs.push(f32::abs(diff*(file_specs.sample_rate as f32))/(2.0*std::f32::consts::PI));
-                            }
-
-                            *freq_buffer.lock().unwrap() = freqs;
-                            *pending_decode.lock().unwrap() = true;
+                            *snr_buffer.lock().unwrap() = track.snr_db;
+                            *freq_buffer.lock().unwrap() = track.freqs;
+                            *sample_rate.lock().unwrap() = track.f_samp;
 
+                            // Set before handing over, or it could overwrite the result of the build.
                             set_status("Building Image...");
-                        });
+                            *pending_decode.lock().unwrap() = true;
+                        }));
 
                     }
                 }
@@ -191,28 +487,115 @@ impl eframe::App for Globals {
                     }
                 });
 
-                if self.frequency_mutex.lock().unwrap().len() > 1 {
-                    if ui.add_sized(egui::vec2(120.0, 30.0), egui::Button::new(RichText::new("Recalculate Image ↻"))).clicked() {
-                        self.main_image = Some(freq_to_img::decode_image(self.frequency_mutex.lock().unwrap().clone(), self.decode_mode.clone()));
-                        self.main_texture_handle = None;
-                    };
+                egui::ComboBox::from_label("Demodulator").selected_text(self.demod_method.to_string()).show_ui(ui, |ui| {
+                    for option in [
+                        DemodMethod::PhaseDifference,
+                        DemodMethod::Pll,
+                        DemodMethod::ZeroCrossing,
+                        ] {
+                        if ui.selectable_value(&mut self.demod_method, option.clone(), option.to_string()).clicked() {
+                            self.demod_method = option;
+                        }
+                    }
+                });
+
+                egui::ComboBox::from_label("Post Filter").selected_text(self.decode_settings.post_filter.to_string()).show_ui(ui, |ui| {
+                    for option in [
+                        PostFilter::None,
+                        PostFilter::MovingAverage,
+                        PostFilter::Median,
+                        PostFilter::LowPass,
+                        ] {
+                        if ui.selectable_value(&mut self.decode_settings.post_filter, option.clone(), option.to_string()).clicked() {
+                            self.decode_settings.post_filter = option;
+                        }
+                    }
+                });
+
+                ui.checkbox(&mut self.decode_settings.flywheel, "Sync Flywheel");
+                ui.checkbox(&mut self.decode_settings.afc, "Frequency Correction");
+
+                if self.frequency_mutex.lock().unwrap().len() > 1
+                    && ui.add_sized(egui::vec2(120.0, 30.0), egui::Button::new(RichText::new("Recalculate Image ↻"))).clicked() {
+                    self.recalculate_image();
+                };
+
+                if !self.sync_confidence.is_empty() {
+                    let mean = self.sync_confidence.iter().sum::<f32>() / self.sync_confidence.len() as f32;
+                    let weak = self.sync_confidence.iter().filter(|&&c| c < 0.5).count();
+                    ui.label(format!("Sync confidence: {:.0}% average, {} weak lines", mean * 100.0, weak));
+                }
+
+                if let Some(offset) = self.freq_offset {
+                    ui.label(format!("Frequency offset: {:+.0} Hz", offset));
+                }
+
+                if let Some(info) = &self.image_info {
+                    if let Some(vis) = info.vis {
+                        ui.label(format!("VIS: {}", vis));
+                    }
+                    if let Some(callsign) = &info.callsign {
+                        ui.label(format!("FSK ID: {}", callsign));
+                    }
+                    if let Some(snr) = info.snr_db {
+                        ui.label(format!("SNR: {:.1} dB", snr));
+                    }
                 }
 
                 ui.separator();
                 ui.heading(RichText::new("Output").size(32.0));
 
-                if let Some(image_data) = &self.main_image {
-                    if ui.add_sized(egui::vec2(120.0, 30.0), egui::Button::new(RichText::new("Save Image 💾"))).clicked() {
-                        let w = image_data.width();
-                        let h = image_data.height();
-                        if let Some(path) = rfd::FileDialog::new()
-                            .add_filter("Image File", &["png"])
-                            .save_file()
-                        {
-                            let buffer: ImageBuffer<Rgba<u8>, _> = ImageBuffer::from_raw(w as u32, h as u32, image_data.as_raw()).expect("Invalid Image Data");
-                            let _ = buffer.save(path);
+                egui::ComboBox::from_label("Image Format").selected_text(self.save_format.to_string()).show_ui(ui, |ui| {
+                    for option in [
+                        ImageFormat::Png,
+                        ImageFormat::Jpeg,
+                        ImageFormat::Bmp,
+                        ImageFormat::WebP,
+                        ImageFormat::Tiff,
+                        ] {
+                        if ui.selectable_value(&mut self.save_format, option.clone(), option.to_string()).clicked() {
+                            self.save_format = option;
                         }
                     }
+                });
+
+                if let Some(image_data) = &self.main_image
+                    && ui.add_sized(egui::vec2(120.0, 30.0), egui::Button::new(RichText::new("Save Image 💾"))).clicked()
+                    && let Some(path) = rfd::FileDialog::new()
+                        .add_filter(format!("{} Image", self.save_format), &[self.save_format.extension()])
+                        .save_file()
+                {
+                    let info = self.image_info.clone().unwrap_or_default();
+                    let status = match save::save_image(&path, image_data, &self.save_format, &info) {
+                        Ok(()) if self.save_format.has_metadata() => String::from("Saved!"),
+                        Ok(()) => String::from("Saved, without metadata"),
+                        Err(e) => format!("Save Failed: {e}"),
+                    };
+                    *self.program_status.lock().unwrap() = status;
+                }
+
+                ui.checkbox(&mut self.auto_save.enabled, "Auto Save");
+
+                if self.auto_save.enabled {
+                    if ui.button("Folder 📁").clicked()
+                        && let Some(directory) = FileDialog::new().pick_folder() {
+                        self.auto_save.directory = Some(directory);
+                    }
+                    match &self.auto_save.directory {
+                        Some(directory) => ui.label(format!("Folder: {}", directory.display())),
+                        None => ui.label("Folder: none chosen"),
+                    };
+                    ui.horizontal(|ui| {
+                        ui.add(egui::TextEdit::singleline(&mut self.auto_save.pattern).desired_width(220.0));
+                        ui.label("Name Pattern");
+                    });
+                    ui.checkbox(&mut self.auto_save.thumbnail, "Thumbnail");
+                    ui.checkbox(&mut self.auto_save.sidecar, "JSON Sidecar");
+                }
+
+                if self.main_image.is_some()
+                    && ui.add_sized(egui::vec2(120.0, 30.0), egui::Button::new(RichText::new("Reply ↩"))).clicked() {
+                    self.start_reply();
                 }
 
             } else {
@@ -220,31 +603,31 @@ impl eframe::App for Globals {
                 ui.separator();
                 ui.heading(RichText::new("Input").size(32.0));
 
-                if ui.add_sized(egui::vec2(120.0, 30.0), egui::Button::new(RichText::new("Select File 📁"))).clicked() {
-                    if let Some(path) = FileDialog::new().add_filter("Image File", &["png"]).pick_file() {
-                        self.encode_load_path = Some(path.display().to_string());
-                        let rgba = image::ImageReader::open(path).expect("Image Reader Failed").decode().expect("Image Decoding Failed").to_rgba8();
-                        let (width, height) = rgba.dimensions();
-                        let pixels: Vec<egui::Color32> = rgba.pixels()
-                            .map(|p| egui::Color32::from_rgba_premultiplied(p[0], p[1], p[2], p[3]))
-                            .collect();
-                        let new_image = egui::ColorImage {
-                            size: [width as usize, height as usize],
-                            source_size: egui::Vec2 { x: (width as f32), y: (height as f32) },
-                            pixels,
-                        };
-                        self.encode_image = Some(new_image);
-                        self.main_image = self.encode_image.clone();
-                        self.main_texture_handle = None;
-                    }
+                if ui.add_sized(egui::vec2(120.0, 30.0), egui::Button::new(RichText::new("Select File 📁"))).clicked()
+                    && let Some(path) = FileDialog::new().add_filter("Image File", &img_to_freq::image_extensions()).pick_file() {
+                    self.load_encode_image(path);
                 }
 
                 if let Some(file_path) = &self.encode_load_path {
                     ui.label(format!("File: {}", file_path)); 
 
-                    if self.encode_image.is_some() {
-                        if ui.add_sized(egui::vec2(120.0, 30.0), egui::Button::new(RichText::new("Encode ⚡").strong())).clicked() {
-                            self.sound_buffer = Some(img_to_freq::encode(self.encode_image.clone().expect("Image Copy Fail"), self.encode_mode.clone()));
+                    if let Some(image_data) = &self.encode_image
+                        && ui.add_sized(egui::vec2(120.0, 30.0), egui::Button::new(RichText::new("Encode ⚡").strong())).clicked() {
+                        if self.overlay_enabled {
+                            match overlay::compose(image_data, &self.encode_mode, &self.encode_settings, &self.overlay_template, &self.overlay_fields) {
+                                Ok(composite) => {
+                                    // Already at the mode's resolution, so fitting again must leave it untouched.
+                                    let settings = img_to_freq::EncodeSettings { fit: Fit::Stretch, crop: None, ..self.encode_settings.clone() };
+                                    self.set_sound(img_to_freq::encode(&composite, self.encode_mode.clone(), &settings));
+                                    self.main_image = Some(composite);
+                                    self.main_texture_handle = None;
+                                }
+                                Err(e) => {
+                                    *self.program_status.lock().unwrap() = format!("Overlay Failed: {e}");
+                                }
+                            }
+                        } else {
+                            self.set_sound(img_to_freq::encode(image_data, self.encode_mode.clone(), &self.encode_settings));
                         }
                     }
                 }
@@ -263,29 +646,226 @@ impl eframe::App for Globals {
                     }
                 });
 
+                egui::ComboBox::from_label("Image Fit").selected_text(self.encode_settings.fit.to_string()).show_ui(ui, |ui| {
+                    for option in [
+                        Fit::Stretch,
+                        Fit::CentreCrop,
+                        Fit::Letterbox,
+                        Fit::Crop,
+                        ] {
+                        if ui.selectable_value(&mut self.encode_settings.fit, option.clone(), option.to_string()).clicked() {
+                            self.encode_settings.fit = option;
+                        }
+                    }
+                });
+
+                match self.encode_settings.fit {
+                    Fit::Letterbox => {
+                        ui.horizontal(|ui| {
+                            ui.color_edit_button_srgb(&mut self.encode_settings.fill.0);
+                            ui.label("Bar Colour");
+                        });
+                    }
+                    Fit::Crop => {
+                        match self.encode_settings.crop {
+                            Some([x, y, w, h]) => {
+                                ui.label(format!("Crop: {w}x{h} at ({x}, {y})"));
+                            }
+                            None => {
+                                ui.label("Drag on the preview to select");
+                            }
+                        }
+                    }
+                    Fit::Stretch | Fit::CentreCrop => {}
+                }
+
+                egui::ComboBox::from_label("Resize Filter").selected_text(self.encode_settings.filter.to_string()).show_ui(ui, |ui| {
+                    for option in [
+                        ResizeFilter::Lanczos3,
+                        ResizeFilter::CatmullRom,
+                        ResizeFilter::Gaussian,
+                        ResizeFilter::Nearest,
+                        ] {
+                        if ui.selectable_value(&mut self.encode_settings.filter, option.clone(), option.to_string()).clicked() {
+                            self.encode_settings.filter = option;
+                        }
+                    }
+                });
+
+                egui::ComboBox::from_label("Sample Rate").selected_text(format!("{} Hz", self.encode_settings.sample_rate)).show_ui(ui, |ui| {
+                    for option in [8000, 11025, 22050, 44100, 48000, 96000] {
+                        ui.selectable_value(&mut self.encode_settings.sample_rate, option, format!("{} Hz", option));
+                    }
+                });
+
+                egui::ComboBox::from_label("Tone Shaping").selected_text(self.encode_settings.shaping.to_string()).show_ui(ui, |ui| {
+                    for option in [
+                        Shaping::None,
+                        Shaping::RaisedCosine,
+                        Shaping::Gaussian,
+                        ] {
+                        if ui.selectable_value(&mut self.encode_settings.shaping, option.clone(), option.to_string()).clicked() {
+                            self.encode_settings.shaping = option;
+                        }
+                    }
+                });
+
+                let mut transition_ms = self.encode_settings.transition_time * 1000.0;
+                if ui.add(egui::Slider::new(&mut transition_ms, 0.02..=0.5).text("Transition (ms)")).changed() {
+                    self.encode_settings.transition_time = transition_ms / 1000.0;
+                }
+                ui.add(egui::Slider::new(&mut self.encode_settings.level_db, -30.0..=0.0).text("Level (dBFS)"));
+                ui.add(egui::Slider::new(&mut self.encode_settings.lead_silence, 0.0..=10.0).text("Lead Silence (s)"));
+                ui.add(egui::Slider::new(&mut self.encode_settings.trail_silence, 0.0..=10.0).text("Trail Silence (s)"));
+
+                egui::ComboBox::from_label("Bit Depth").selected_text(self.encode_settings.bit_depth.to_string()).show_ui(ui, |ui| {
+                    for option in [
+                        BitDepth::Int8,
+                        BitDepth::Int16,
+                        BitDepth::Int24,
+                        BitDepth::Float32,
+                        ] {
+                        if ui.selectable_value(&mut self.encode_settings.bit_depth, option.clone(), option.to_string()).clicked() {
+                            self.encode_settings.bit_depth = option;
+                        }
+                    }
+                });
+
+                egui::ComboBox::from_label("Channels").selected_text(self.encode_settings.channels.to_string()).show_ui(ui, |ui| {
+                    for option in [
+                        Channels::Mono,
+                        Channels::Stereo,
+                        Channels::LeftOnly,
+                        Channels::RightOnly,
+                        ] {
+                        if ui.selectable_value(&mut self.encode_settings.channels, option.clone(), option.to_string()).clicked() {
+                            self.encode_settings.channels = option;
+                        }
+                    }
+                });
+
                 ui.separator();
-                ui.heading(RichText::new("Output").size(32.0));
+                ui.heading(RichText::new("Overlay").size(32.0));
 
-                if let Some(sound_samples) = &self.sound_buffer {
-                    if ui.add_sized(egui::vec2(120.0, 30.0), egui::Button::new(RichText::new("Save Image 💾"))).clicked() {
-                        if let Some(path) = rfd::FileDialog::new()
-                            .add_filter(".wav File", &["wav"])
-                            .save_file()
-                        {
-                            let wave_spec = hound::WavSpec {
-                                channels: 1,
-                                sample_rate: 44100,
-                                bits_per_sample: 16,
-                                sample_format: hound::SampleFormat::Int,
-                            };
+                ui.checkbox(&mut self.overlay_enabled, "Text Overlay");
+
+                if self.overlay_enabled {
+                    for (value, label) in [
+                        (&mut self.overlay_fields.callsign, "Callsign"),
+                        (&mut self.overlay_fields.mycall, "My Call"),
+                        (&mut self.overlay_fields.report, "Report"),
+                        (&mut self.overlay_fields.grid, "Grid Square"),
+                        ] {
+                        ui.horizontal(|ui| {
+                            ui.add(egui::TextEdit::singleline(value).desired_width(90.0));
+                            ui.label(label);
+                        });
+                    }
 
-                            let mut writer = hound::WavWriter::create(path, wave_spec).unwrap();
-                            for p in sound_samples {
-                                let _ = writer.write_sample(*p);
+                    let mut remove_layer = None;
+                    for (i, layer) in self.overlay_template.layers.iter_mut().enumerate() {
+                        egui::CollapsingHeader::new(format!("Layer {}", i + 1)).id_salt(i).show(ui, |ui| {
+                            ui.add(egui::TextEdit::multiline(&mut layer.text).desired_rows(1).desired_width(180.0));
+                            ui.add(egui::Slider::new(&mut layer.size, 8.0..=96.0).text("Size (px)"));
+                            ui.add(egui::Slider::new(&mut layer.position[0], 0.0..=1.0).text("Horizontal"));
+                            ui.add(egui::Slider::new(&mut layer.position[1], 0.0..=1.0).text("Vertical"));
+                            ui.horizontal(|ui| {
+                                ui.color_edit_button_srgba_unmultiplied(&mut layer.colour);
+                                ui.label("Text Colour");
+                            });
+                            ui.horizontal(|ui| {
+                                ui.color_edit_button_srgba_unmultiplied(&mut layer.outline);
+                                ui.label("Outline Colour");
+                            });
+                            ui.add(egui::Slider::new(&mut layer.outline_width, 0.0..=6.0).text("Outline (px)"));
+                            ui.horizontal(|ui| {
+                                if ui.button("Font 🔤").clicked()
+                                    && let Some(path) = FileDialog::new().add_filter("Font File", &["ttf", "otf"]).pick_file() {
+                                    layer.font = Some(path.display().to_string());
+                                }
+                                if layer.font.is_some() && ui.button("Built-in Font").clicked() {
+                                    layer.font = None;
+                                }
+                            });
+                            if let Some(font) = &layer.font {
+                                ui.label(format!("Font: {}", font));
                             }
-                            let _ = writer.finalize();
-                        }
+                            if ui.button("Remove Layer").clicked() {
+                                remove_layer = Some(i);
+                            }
+                        });
+                    }
+                    if let Some(i) = remove_layer {
+                        self.overlay_template.layers.remove(i);
                     }
+
+                    ui.horizontal(|ui| {
+                        if ui.button("Add Layer").clicked() {
+                            self.overlay_template.layers.push(TextLayer::default());
+                        }
+                        if self.encode_image.is_some() && ui.button("Preview 👁").clicked() {
+                            self.preview_overlay();
+                        }
+                    });
+
+                    egui::CollapsingHeader::new("Reply Inset").show(ui, |ui| {
+                        let inset = &mut self.overlay_template.inset;
+                        ui.add(egui::Slider::new(&mut inset.scale, 0.1..=1.0).text("Scale"));
+                        ui.add(egui::Slider::new(&mut inset.position[0], 0.0..=1.0).text("Horizontal"));
+                        ui.add(egui::Slider::new(&mut inset.position[1], 0.0..=1.0).text("Vertical"));
+                        ui.add(egui::Slider::new(&mut inset.border, 0.0..=8.0).text("Border (px)"));
+                        ui.horizontal(|ui| {
+                            ui.color_edit_button_srgba_unmultiplied(&mut inset.border_colour);
+                            ui.label("Border Colour");
+                        });
+                        ui.horizontal(|ui| {
+                            if ui.button("Background 🖼").clicked()
+                                && let Some(path) = FileDialog::new().add_filter("Image File", &img_to_freq::image_extensions()).pick_file() {
+                                self.overlay_template.background = Some(path.display().to_string());
+                            }
+                            if self.overlay_template.background.is_some() && ui.button("Plain Fill").clicked() {
+                                self.overlay_template.background = None;
+                            }
+                        });
+                        match &self.overlay_template.background {
+                            Some(background) => ui.label(format!("Background: {}", background)),
+                            None => ui.label("Background: plain fill"),
+                        };
+                    });
+
+                    ui.horizontal(|ui| {
+                        if ui.button("Load Template 📁").clicked()
+                            && let Some(path) = FileDialog::new().add_filter("Overlay Template", &["json"]).pick_file() {
+                            match OverlayTemplate::load(&path) {
+                                Ok(template) => self.overlay_template = template,
+                                Err(e) => *self.program_status.lock().unwrap() = format!("Load Failed: {e}"),
+                            }
+                        }
+                        if ui.button("Save Template 💾").clicked()
+                            && let Some(path) = FileDialog::new().add_filter("Overlay Template", &["json"]).save_file() {
+                            let status = match self.overlay_template.save(&path) {
+                                Ok(()) => String::from("Saved!"),
+                                Err(e) => format!("Save Failed: {e}"),
+                            };
+                            *self.program_status.lock().unwrap() = status;
+                        }
+                    });
+                }
+
+                ui.separator();
+                ui.heading(RichText::new("Output").size(32.0));
+
+                if let Some(sound_samples) = &self.sound_buffer
+                    && ui.add_sized(egui::vec2(120.0, 30.0), egui::Button::new(RichText::new("Save Image 💾"))).clicked()
+                    && let Some(path) = rfd::FileDialog::new()
+                        .add_filter(".wav File", &["wav"])
+                        .save_file()
+                {
+                    let status = match audio::write_wav(&path, sound_samples.iter().copied(), self.sound_rate, &self.encode_settings.bit_depth, &self.encode_settings.channels) {
+                        Ok(()) => String::from("Saved!"),
+                        Err(e) => format!("Save Failed: {e}"),
+                    };
+                    *self.program_status.lock().unwrap() = status;
                 }
 
 
@@ -293,14 +873,110 @@ impl eframe::App for Globals {
             }
 
             ui.with_layout(egui::Layout::bottom_up(egui::Align::Center),|ui| {
-                ui.heading(RichText::new(format!("{}", *self.program_status.lock().unwrap())).size(24.0));
+                ui.heading(RichText::new((*self.program_status.lock().unwrap()).to_string()).size(24.0));
             }) 
         });
 
+        if self.show_decode_panel
+            && let (Some(waterfall), Some(texture)) = (&self.waterfall, &self.waterfall_texture) {
+            let duration = waterfall.duration;
+            let bins = waterfall.rows.first().map_or(1, Vec::len) as f32;
+            let bin_width = waterfall.bin_width;
+            let texture_id = texture.id();
+            let mut rebuild = false;
+
+            egui::SidePanel::right("waterfall").min_width(260.0).frame(egui::Frame::new().fill(egui::Color32::from_rgb(20, 20, 21)).inner_margin(15.0)).show(ctx, |ui| {
+                ui.heading(RichText::new("Waterfall").size(32.0));
+                ui.label("Drag to choose the part of the recording to decode");
+
+                let size = egui::vec2(ui.available_width(), f32::max(100.0, ui.available_height() - 70.0));
+                let response = ui.add(egui::Image::new((texture_id, size)).sense(egui::Sense::click_and_drag()));
+                let rect = response.rect;
+                // Pixel k is the bin centred on k * bin_width.
+                let to_x = |f: f32| rect.left() + (f / bin_width + 0.5) / bins * rect.width();
+                let to_time = |pos: egui::Pos2| ((pos.y - rect.top()) / rect.height()).clamp(0.0, 1.0) * duration;
+                let to_y = |t: f32| rect.top() + t / duration * rect.height();
+
+                for marker in waterfall::MARKERS {
+                    let x = to_x(marker);
+                    ui.painter().line_segment([egui::pos2(x, rect.top()), egui::pos2(x, rect.bottom())], egui::Stroke::new(1.0, egui::Color32::from_rgba_unmultiplied(0, 255, 128, 160)));
+                    ui.painter().text(egui::pos2(x + 2.0, rect.top() + 2.0), egui::Align2::LEFT_TOP, format!("{marker:.0}"), egui::FontId::proportional(11.0), egui::Color32::from_rgb(0, 255, 128));
+                }
+
+                if response.drag_started() {
+                    self.segment_drag_start = response.interact_pointer_pos().map(to_time);
+                }
+                if let (Some(start), Some(pos)) = (self.segment_drag_start, response.interact_pointer_pos())
+                    && response.dragged() {
+                    let end = to_time(pos);
+                    self.segment = Some([start.min(end), start.max(end)]);
+                }
+                if response.drag_stopped() {
+                    self.segment_drag_start = None;
+                    rebuild = true;
+                }
+                // A click alone starts the segment there and runs it to the end.
+                if response.clicked()
+                    && let Some(pos) = response.interact_pointer_pos() {
+                    self.segment = Some([to_time(pos), duration]);
+                    rebuild = true;
+                }
+
+                if let Some([start, end]) = self.segment {
+                    let selection = egui::Rect::from_x_y_ranges(rect.x_range(), to_y(start)..=to_y(end));
+                    ui.painter().rect_filled(selection, 0.0, egui::Color32::from_rgba_unmultiplied(255, 255, 0, 30));
+                    ui.painter().rect_stroke(selection, 0.0, egui::Stroke::new(2.0, egui::Color32::YELLOW), egui::StrokeKind::Middle);
+                    ui.label(format!("Segment: {start:.1} s to {end:.1} s"));
+                } else {
+                    ui.label(format!("Segment: whole recording, {duration:.1} s"));
+                }
+
+                if self.segment.is_some() && ui.button("Whole Recording").clicked() {
+                    self.segment = None;
+                    rebuild = true;
+                }
+            });
+
+            if rebuild && !self.is_decoding {
+                self.recalculate_image();
+            }
+        }
+
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.centered_and_justified(|ui| {
                 if let Some(texture) = &self.main_texture_handle {
-                    ui.image((texture.id(), egui::vec2(800.0, 600.0)));
+                    let selecting = !self.show_decode_panel && self.encode_settings.fit == Fit::Crop;
+                    let sense = if selecting { egui::Sense::drag() } else { egui::Sense::hover() };
+                    let response = ui.add(egui::Image::new((texture.id(), egui::vec2(800.0, 600.0))).sense(sense));
+
+                    if selecting && let Some(image_data) = &self.encode_image {
+                        let rect = response.rect;
+                        let image_size = egui::vec2(image_data.width() as f32, image_data.height() as f32);
+                        let to_image = |pos: egui::Pos2| ((pos - rect.min) / rect.size() * image_size).clamp(egui::Vec2::ZERO, image_size).to_pos2();
+
+                        if response.drag_started() {
+                            self.crop_drag_start = response.interact_pointer_pos().map(to_image);
+                        }
+                        if let (Some(start), Some(pos)) = (self.crop_drag_start, response.interact_pointer_pos())
+                            && response.dragged() {
+                            let selection = egui::Rect::from_two_pos(start, to_image(pos));
+                            self.encode_settings.crop = Some([
+                                selection.min.x as u32,
+                                selection.min.y as u32,
+                                u32::max(1, selection.width() as u32),
+                                u32::max(1, selection.height() as u32),
+                            ]);
+                        }
+                        if response.drag_stopped() {
+                            self.crop_drag_start = None;
+                        }
+
+                        if let Some([x, y, w, h]) = self.encode_settings.crop {
+                            let to_screen = |px: f32, py: f32| rect.min + egui::vec2(px, py) / image_size * rect.size();
+                            let selection = egui::Rect::from_min_max(to_screen(x as f32, y as f32), to_screen((x + w) as f32, (y + h) as f32));
+                            ui.painter().rect_stroke(selection, 0.0, egui::Stroke::new(2.0, egui::Color32::YELLOW), egui::StrokeKind::Middle);
+                        }
+                    }
                 }
             })
         });
@@ -308,6 +984,12 @@ impl eframe::App for Globals {
 }
 
 fn main() {
+    // With arguments, run headless so recordings can be processed from scripts.
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        std::process::exit(cli::run(&args));
+    }
+
     let icon_data = {
         let bytes = include_bytes!("app_icon.png");
         let img = image::load_from_memory(bytes).expect("failed to load").to_rgba8();
@@ -316,6 +998,7 @@ fn main() {
 
     let viewport = egui::ViewportBuilder::default()
     .with_inner_size(egui::vec2(1200.0, 800.0))
+    .with_drag_and_drop(true)
     .with_icon(icon_data);
 
     let options = eframe::NativeOptions {
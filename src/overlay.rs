@@ -0,0 +1,420 @@
+use std::path::Path;
+use ab_glyph::{Font, FontArc, PxScale, ScaleFont, point};
+use image::{Rgb, RgbImage};
+use serde::{Deserialize, Serialize};
+use crate::{Error, Mode, Result};
+use crate::img_to_freq::{self, EncodeSettings};
+
+// Used for layers that do not name a font; its licence is in resources/Hack-Regular.txt.
+const DEFAULT_FONT: &[u8] = include_bytes!("../resources/Hack-Regular.ttf");
+
+/// One line (or several, split on `\n`) of text drawn over the picture.
+#[derive(Clone)]
+#[derive(Serialize, Deserialize)]
+pub struct TextLayer {
+    /// May contain `{callsign}`, `{mycall}`, `{report}`, `{grid}`, `{date}` and `{time}`.
+    pub text: String,
+    /// TrueType or OpenType file to draw with; the built-in font is used when empty.
+    pub font: Option<String>,
+    /// Height of the text in pixels at the mode's resolution.
+    pub size: f32,
+    /// Unmultiplied RGBA, so the alpha sets the opacity.
+    pub colour: [u8; 4],
+    pub outline: [u8; 4],
+    /// Outline thickness in pixels; zero draws no outline.
+    pub outline_width: f32,
+    /// Where the text sits as a fraction of the free space: `[0, 0]` is the top left corner,
+    /// `[1, 1]` the bottom right and `[0.5, 0.5]` the centre.
+    pub position: [f32; 2],
+}
+
+impl Default for TextLayer {
+    fn default() -> Self {
+        Self {
+            text: String::from("{callsign} de {mycall}"),
+            font: None,
+            size: 32.0,
+            colour: [255, 255, 255, 255],
+            outline: [0, 0, 0, 255],
+            outline_width: 2.0,
+            position: [0.05, 0.05],
+        }
+    }
+}
+
+/// Where a received picture goes when replying to it.
+#[derive(Clone)]
+#[derive(Serialize, Deserialize)]
+pub struct Inset {
+    /// Width of the received picture as a fraction of ours; its aspect ratio is kept.
+    pub scale: f32,
+    /// Placed the same way as `TextLayer::position`.
+    pub position: [f32; 2],
+    /// Border thickness in pixels; zero draws no border.
+    pub border: f32,
+    pub border_colour: [u8; 4],
+}
+
+impl Default for Inset {
+    fn default() -> Self {
+        Self {
+            scale: 0.45,
+            position: [0.95, 0.5],
+            border: 2.0,
+            border_colour: [255, 255, 255, 255],
+        }
+    }
+}
+
+/// A set of layers that can be saved and reused for every transmission.
+#[derive(Clone)]
+#[derive(Serialize, Deserialize)]
+pub struct OverlayTemplate {
+    pub layers: Vec<TextLayer>,
+    /// Picture that replies are built on; a plain fill is used when empty.
+    #[serde(default)]
+    pub background: Option<String>,
+    #[serde(default)]
+    pub inset: Inset,
+}
+
+impl Default for OverlayTemplate {
+    fn default() -> Self {
+        Self {
+            layers: vec![
+                TextLayer::default(),
+                TextLayer {
+                    text: String::from("RSV {report}"),
+                    colour: [255, 255, 0, 255],
+                    position: [0.05, 0.95],
+                    ..Default::default()
+                },
+                TextLayer {
+                    text: String::from("{date} {time}\n{grid}"),
+                    size: 18.0,
+                    outline_width: 1.5,
+                    position: [0.95, 0.95],
+                    ..Default::default()
+                },
+            ],
+            background: None,
+            inset: Inset::default(),
+        }
+    }
+}
+
+impl OverlayTemplate {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&text)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        Ok(std::fs::write(path, serde_json::to_string_pretty(self)?)?)
+    }
+}
+
+/// Values substituted into the layer text when the overlay is drawn.
+#[derive(Clone, Default)]
+pub struct OverlayFields {
+    /// The station being called.
+    pub callsign: String,
+    /// Our own callsign, sent after "de".
+    pub mycall: String,
+    /// Readability, strength and vision report, e.g. "595".
+    pub report: String,
+    pub grid: String,
+}
+
+impl OverlayFields {
+    /// Replaces the placeholders in `text`, taking the date and time in UTC.
+    pub fn expand(&self, text: &str) -> String {
+        let now = chrono::Utc::now();
+        text.replace("{callsign}", &self.callsign)
+            .replace("{mycall}", &self.mycall)
+            .replace("{report}", &self.report)
+            .replace("{grid}", &self.grid)
+            .replace("{date}", &now.format("%Y-%m-%d").to_string())
+            .replace("{time}", &now.format("%H:%Mz").to_string())
+    }
+}
+
+fn load_font(path: &Option<String>) -> Result<FontArc> {
+    let font = match path {
+        Some(path) => FontArc::try_from_vec(std::fs::read(path)?),
+        None => FontArc::try_from_slice(DEFAULT_FONT),
+    };
+    font.map_err(|e| Error::Font(e.to_string()))
+}
+
+// Blends `colour` over `pix` with the given coverage.
+fn blend(pix: Rgb<u8>, colour: [u8; 4], coverage: f32) -> Rgb<u8> {
+    let alpha = coverage.clamp(0.0, 1.0) * colour[3] as f32 / 255.0;
+    let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * alpha).round() as u8;
+    Rgb([mix(pix[0], colour[0]), mix(pix[1], colour[1]), mix(pix[2], colour[2])])
+}
+
+/// Draws one layer onto `image`.
+pub fn draw_layer(image: &mut RgbImage, layer: &TextLayer, fields: &OverlayFields) -> Result<()> {
+    let font = load_font(&layer.font)?;
+    let scaled = font.as_scaled(PxScale::from(layer.size));
+    let text = fields.expand(&layer.text);
+    let lines: Vec<&str> = text.lines().collect();
+    let line_height = scaled.height() + scaled.line_gap();
+
+    let line_width = |line: &str| {
+        let mut width = 0.0;
+        let mut previous = None;
+        for c in line.chars() {
+            let id = scaled.glyph_id(c);
+            if let Some(previous) = previous {
+                width += scaled.kern(previous, id);
+            }
+            width += scaled.h_advance(id);
+            previous = Some(id);
+        }
+        width
+    };
+
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let margin = layer.outline_width.max(0.0);
+    let box_width = lines.iter().map(|line| line_width(line)).fold(0.0, f32::max) + 2.0 * margin;
+    let box_height = lines.len() as f32 * line_height + 2.0 * margin;
+    let left = layer.position[0] * (width as f32 - box_width) + margin;
+    let top = layer.position[1] * (height as f32 - box_height) + margin;
+
+    // Coverage of the glyphs themselves, later grown to make the outline.
+    let mut mask = vec![0.0f32; width * height];
+    for (n, line) in lines.iter().enumerate() {
+        let baseline = top + n as f32 * line_height + scaled.ascent();
+        // Lines after the first are aligned the same way as the block as a whole.
+        let mut x = left + layer.position[0] * (box_width - 2.0 * margin - line_width(line));
+        let mut previous = None;
+        for c in line.chars() {
+            let id = scaled.glyph_id(c);
+            if let Some(previous) = previous {
+                x += scaled.kern(previous, id);
+            }
+            let glyph = id.with_scale_and_position(layer.size, point(x, baseline));
+            if let Some(outlined) = font.outline_glyph(glyph) {
+                let bounds = outlined.px_bounds();
+                outlined.draw(|gx, gy, coverage| {
+                    let px = bounds.min.x as i64 + gx as i64;
+                    let py = bounds.min.y as i64 + gy as i64;
+                    if px >= 0 && py >= 0 && (px as usize) < width && (py as usize) < height {
+                        let m = &mut mask[py as usize * width + px as usize];
+                        *m = f32::max(*m, coverage);
+                    }
+                });
+            }
+            x += scaled.h_advance(id);
+            previous = Some(id);
+        }
+    }
+
+    if layer.outline_width > 0.0 {
+        let radius = layer.outline_width.ceil() as i64;
+        let mut outline = vec![0.0f32; width * height];
+        for y in 0..height as i64 {
+            for x in 0..width as i64 {
+                let mut grown: f32 = 0.0;
+                for dy in -radius..=radius {
+                    for dx in -radius..=radius {
+                        let (sx, sy) = (x + dx, y + dy);
+                        if sx < 0 || sy < 0 || sx >= width as i64 || sy >= height as i64 {
+                            continue
+                        }
+                        // Soft edge so the outline is anti-aliased like the glyphs.
+                        let reach = layer.outline_width + 0.5 - f32::hypot(dx as f32, dy as f32);
+                        grown = grown.max(mask[sy as usize * width + sx as usize] * reach.clamp(0.0, 1.0));
+                    }
+                }
+                outline[y as usize * width + x as usize] = grown;
+            }
+        }
+        for (pix, coverage) in image.pixels_mut().zip(&outline) {
+            if *coverage > 0.0 {
+                *pix = blend(*pix, layer.outline, *coverage);
+            }
+        }
+    }
+
+    for (pix, coverage) in image.pixels_mut().zip(&mask) {
+        if *coverage > 0.0 {
+            *pix = blend(*pix, layer.colour, *coverage);
+        }
+    }
+
+    Ok(())
+}
+
+/// Builds the picture for replying to `received`: the template background fitted to the mode's
+/// resolution, with the received picture scaled into the template's inset. Text is left to `compose`.
+pub fn reply(received: &RgbImage, mode: &Mode, settings: &EncodeSettings, template: &OverlayTemplate) -> Result<RgbImage> {
+    let (width, height) = mode.resolution();
+    let settings = EncodeSettings { crop: None, ..settings.clone() };
+
+    let mut image = match &template.background {
+        Some(path) => {
+            let background = img_to_freq::load_image(Path::new(path))?;
+            img_to_freq::fit(&background, width as u32, height as u32, &settings)
+        }
+        None => RgbImage::from_pixel(width as u32, height as u32, settings.fill),
+    };
+
+    let inset = &template.inset;
+    let aspect = received.height() as f32 / received.width().max(1) as f32;
+    let inset_width = ((inset.scale * width as f32).round() as u32).clamp(1, width as u32);
+    let inset_height = ((inset_width as f32 * aspect).round() as u32).clamp(1, height as u32);
+    let scaled = image::imageops::resize(received, inset_width, inset_height, settings.filter.filter_type());
+
+    let border = inset.border.max(0.0);
+    let left = inset.position[0] * (width as f32 - inset_width as f32 - 2.0 * border) + border;
+    let top = inset.position[1] * (height as f32 - inset_height as f32 - 2.0 * border) + border;
+    let (left, top) = (left.round() as i64, top.round() as i64);
+    let reach = border.round() as i64;
+
+    for y in (top - reach)..(top + inset_height as i64 + reach) {
+        for x in (left - reach)..(left + inset_width as i64 + reach) {
+            if x < 0 || y < 0 || x >= width as i64 || y >= height as i64 {
+                continue
+            }
+            let pix = image.get_pixel_mut(x as u32, y as u32);
+            let (ix, iy) = (x - left, y - top);
+            if ix >= 0 && iy >= 0 && ix < inset_width as i64 && iy < inset_height as i64 {
+                *pix = *scaled.get_pixel(ix as u32, iy as u32);
+            } else {
+                *pix = blend(*pix, inset.border_colour, 1.0);
+            }
+        }
+    }
+
+    Ok(image)
+}
+
+/// Fits the picture to the mode's resolution and draws every layer of `template` on top,
+/// so text sizes are in transmitted pixels whatever the source picture was.
+pub fn compose(image_data: &RgbImage, mode: &Mode, settings: &EncodeSettings, template: &OverlayTemplate, fields: &OverlayFields) -> Result<RgbImage> {
+    let (width, height) = mode.resolution();
+    let mut image = img_to_freq::fit(image_data, width as u32, height as u32, settings);
+
+    for layer in &template.layers {
+        draw_layer(&mut image, layer, fields)?;
+    }
+
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields() -> OverlayFields {
+        OverlayFields {
+            callsign: String::from("W1AW"),
+            mycall: String::from("G4ABC"),
+            report: String::from("595"),
+            grid: String::from("IO91"),
+        }
+    }
+
+    // Pixels that are no longer the black they started as, within the given rectangle.
+    fn lit(image: &RgbImage, x: std::ops::Range<u32>, y: std::ops::Range<u32>) -> usize {
+        y.flat_map(|y| x.clone().map(move |x| (x, y))).filter(|&(x, y)| image[(x, y)] != Rgb([0, 0, 0])).count()
+    }
+
+    #[test]
+    fn placeholders_are_expanded() {
+        assert_eq!(fields().expand("{callsign} de {mycall} RSV {report} {grid}"), "W1AW de G4ABC RSV 595 IO91");
+        assert_eq!(fields().expand("no placeholders {unknown}"), "no placeholders {unknown}");
+
+        let date = fields().expand("{date}");
+        assert!(date.len() == 10 && &date[4..5] == "-" && &date[7..8] == "-", "{date}");
+        let time = fields().expand("{time}");
+        assert!(time.len() == 6 && &time[2..3] == ":" && time.ends_with('z'), "{time}");
+    }
+
+    #[test]
+    fn layer_is_drawn_where_it_is_placed() {
+        let layer = TextLayer { text: String::from("{callsign}"), outline_width: 0.0, position: [0.0, 0.0], ..Default::default() };
+        let mut image = RgbImage::new(320, 256);
+        draw_layer(&mut image, &layer, &fields()).unwrap();
+        assert!(image.pixels().any(|pixel| *pixel == Rgb([255, 255, 255])));
+        assert_eq!(lit(&image, 0..320, 64..256), 0);
+
+        let layer = TextLayer { position: [1.0, 1.0], ..layer };
+        let mut image = RgbImage::new(320, 256);
+        draw_layer(&mut image, &layer, &fields()).unwrap();
+        assert!(lit(&image, 160..320, 192..256) > 0);
+        assert_eq!(lit(&image, 0..320, 0..192), 0);
+    }
+
+    #[test]
+    fn outline_surrounds_the_text() {
+        let layer = TextLayer {
+            text: String::from("SSTV"),
+            colour: [255, 255, 255, 255],
+            outline: [255, 0, 0, 255],
+            outline_width: 2.0,
+            ..Default::default()
+        };
+        let mut image = RgbImage::new(320, 256);
+        draw_layer(&mut image, &layer, &fields()).unwrap();
+        assert!(image.pixels().any(|pixel| *pixel == Rgb([255, 0, 0])));
+        assert!(image.pixels().any(|pixel| *pixel == Rgb([255, 255, 255])));
+
+        // Half transparent text only goes halfway to its colour.
+        let layer = TextLayer { colour: [255, 255, 255, 128], outline_width: 0.0, ..layer };
+        let mut image = RgbImage::new(320, 256);
+        draw_layer(&mut image, &layer, &fields()).unwrap();
+        assert!(image.pixels().all(|pixel| pixel[0] <= 128));
+        assert!(image.pixels().any(|pixel| pixel[0] == 128));
+    }
+
+    #[test]
+    fn reply_places_the_inset_inside_its_border() {
+        let received = RgbImage::from_pixel(100, 50, Rgb([255, 0, 0]));
+        let settings = EncodeSettings { fill: Rgb([0, 0, 255]), ..Default::default() };
+        let template = OverlayTemplate {
+            inset: Inset { scale: 0.5, position: [1.0, 0.0], border: 2.0, border_colour: [255, 255, 255, 255] },
+            ..Default::default()
+        };
+
+        // Half of 320 wide keeps the 2:1 shape at 160 x 80, in the top right inside the 2 pixel border.
+        let image = reply(&received, &Mode::MartinM1, &settings, &template).unwrap();
+        assert_eq!(image.dimensions(), (320, 256));
+        assert_eq!(image[(0, 0)], Rgb([0, 0, 255]));
+        assert_eq!(image[(155, 40)], Rgb([0, 0, 255]));
+        for (x, y) in [(156, 40), (157, 40), (318, 40), (319, 40), (200, 0), (200, 1), (200, 82), (200, 83)] {
+            assert_eq!(image[(x, y)], Rgb([255, 255, 255]), "border at {x}, {y}");
+        }
+        for (x, y) in [(158, 2), (317, 2), (158, 81), (317, 81)] {
+            assert_eq!(image[(x, y)], Rgb([255, 0, 0]), "inset at {x}, {y}");
+        }
+        assert_eq!(image[(200, 84)], Rgb([0, 0, 255]));
+
+        // Centred, without a border.
+        let template = OverlayTemplate { inset: Inset { position: [0.5, 0.5], border: 0.0, ..template.inset }, ..template };
+        let image = reply(&received, &Mode::MartinM1, &settings, &template).unwrap();
+        assert_eq!(image[(79, 128)], Rgb([0, 0, 255]));
+        assert_eq!(image[(80, 88)], Rgb([255, 0, 0]));
+        assert_eq!(image[(239, 167)], Rgb([255, 0, 0]));
+        assert_eq!(image[(240, 128)], Rgb([0, 0, 255]));
+        assert_eq!(image[(160, 168)], Rgb([0, 0, 255]));
+    }
+
+    #[test]
+    fn template_survives_json() {
+        let template = OverlayTemplate { background: Some(String::from("background.png")), ..Default::default() };
+        let json = serde_json::to_string_pretty(&template).unwrap();
+        let read: OverlayTemplate = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string_pretty(&read).unwrap(), json);
+        assert_eq!(read.layers.len(), 3);
+        assert_eq!(read.layers[1].text, "RSV {report}");
+
+        // Templates saved before replies existed have no background or inset.
+        let read: OverlayTemplate = serde_json::from_str(r#"{"layers": []}"#).unwrap();
+        assert!(read.background.is_none());
+        assert_eq!(read.inset.scale, Inset::default().scale);
+    }
+}
@@ -0,0 +1,127 @@
+/// Rate every recording is converted to before demodulation, so the decoder always sees the same timing.
+pub const WORKING_RATE: u32 = 22050;
+
+// Zero crossings of the sinc kernel on each side of the centre, at the lower of the two rates.
+const KERNEL_ZEROS: f32 = 16.0;
+const KAISER_BETA: f32 = 8.0;
+
+fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+fn sinc(x: f32) -> f32 {
+    if x == 0.0 {
+        1.0
+    } else {
+        let px = std::f32::consts::PI * x;
+        px.sin() / px
+    }
+}
+
+// Zeroth order modified Bessel function of the first kind, for the Kaiser window.
+fn bessel_i0(x: f32) -> f32 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half = x / 2.0;
+    for k in 1..32 {
+        term *= (half / k as f32) * (half / k as f32);
+        sum += term;
+        if term < 1e-9 * sum {
+            break
+        }
+    }
+    sum
+}
+
+fn kaiser(x: f32) -> f32 {
+    if x.abs() >= 1.0 {
+        0.0
+    } else {
+        bessel_i0(KAISER_BETA * f32::sqrt(1.0 - x * x)) / bessel_i0(KAISER_BETA)
+    }
+}
+
+/// Converts `samples` from `from` Hz to `to` Hz with a Kaiser windowed sinc polyphase filter.
+/// When reducing the rate, the filter cutoff follows the new Nyquist frequency so nothing aliases.
+pub fn resample(samples: &[f32], from: u32, to: u32) -> Vec<f32> {
+    if from == to || samples.is_empty() {
+        return samples.to_vec()
+    }
+
+    let g = gcd(from, to);
+    let up = (to / g) as u64;
+    let down = (from / g) as u64;
+
+    // Cutoff as a fraction of the input Nyquist frequency, with a little room for the transition band.
+    let cutoff = f32::min(1.0, to as f32 / from as f32) * 0.95;
+    let half_taps = f32::ceil(KERNEL_ZEROS / cutoff) as i64;
+    let taps = (2 * half_taps) as usize;
+
+    // One row of taps per output phase, each row covering input offsets -half_taps+1..=half_taps.
+    let mut table: Vec<f32> = Vec::with_capacity(up as usize * taps);
+    for phase in 0..up {
+        let frac = phase as f32 / up as f32;
+        for k in (1 - half_taps)..=half_taps {
+            let tau = k as f32 - frac;
+            table.push(cutoff * sinc(cutoff * tau) * kaiser(tau / half_taps as f32));
+        }
+    }
+
+    let out_len = (samples.len() as u64 * up / down) as usize;
+    let mut output: Vec<f32> = Vec::with_capacity(out_len);
+
+    for n in 0..out_len as u64 {
+        let position = n * down;
+        let base = (position / up) as i64;
+        let phase = (position % up) as usize;
+        let coeffs = &table[phase * taps..(phase + 1) * taps];
+
+        let mut acc = 0.0;
+        for (j, c) in coeffs.iter().enumerate() {
+            let idx = base + j as i64 + 1 - half_taps;
+            if idx >= 0 && (idx as usize) < samples.len() {
+                acc += c * samples[idx as usize];
+            }
+        }
+        output.push(acc);
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // One second of a 1500 Hz tone at `from`, resampled to `to`, checking the length, and the
+    // frequency and level away from the edges, where the filter runs off the recording.
+    fn keeps_a_tone(from: u32, to: u32) {
+        let samples: Vec<f32> = (0..from).map(|n| (std::f32::consts::TAU * 1500.0 * n as f32 / from as f32).sin()).collect();
+        let output = resample(&samples, from, to);
+        assert_eq!(output.len(), to as usize, "{from} to {to}");
+
+        // Count rising zero crossings.
+        let middle = &output[to as usize / 10..to as usize * 9 / 10];
+        let crossings = middle.windows(2).filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0).count();
+        let frequency = crossings as f32 * to as f32 / middle.len() as f32;
+        assert!((frequency - 1500.0).abs() < 5.0, "{from} to {to}: {frequency} Hz");
+
+        let peak = middle.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        assert!((peak - 1.0).abs() < 0.05, "{from} to {to}: peak {peak}");
+    }
+
+    #[test]
+    fn halving_the_rate_keeps_a_tone() {
+        keeps_a_tone(44100, 22050);
+    }
+
+    #[test]
+    fn uneven_ratio_keeps_a_tone() {
+        keeps_a_tone(48000, 22050);
+    }
+
+    #[test]
+    fn upsampling_keeps_a_tone() {
+        keeps_a_tone(8000, 22050);
+    }
+}
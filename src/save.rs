@@ -0,0 +1,401 @@
+use std::fs::File;
+use std::io::{BufWriter, Seek, Write};
+use std::path::{Path, PathBuf};
+use image::RgbImage;
+use image::ImageEncoder;
+use serde::Serialize;
+use crate::{Error, Mode, Result};
+use crate::demod::DemodMethod;
+use crate::freq_to_img::{DecodeSettings, DecodedImage};
+
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+    Bmp,
+    WebP,
+    Tiff,
+}
+
+impl std::fmt::Display for ImageFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let label = match self {
+            ImageFormat::Png => "PNG",
+            ImageFormat::Jpeg => "JPEG",
+            ImageFormat::Bmp => "BMP",
+            ImageFormat::WebP => "WebP",
+            ImageFormat::Tiff => "TIFF",
+        };
+        write!(f, "{label}")
+    }
+}
+
+impl ImageFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Jpeg => "jpg",
+            ImageFormat::Bmp => "bmp",
+            ImageFormat::WebP => "webp",
+            ImageFormat::Tiff => "tif",
+        }
+    }
+
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "png" => Some(ImageFormat::Png),
+            "jpg" | "jpeg" => Some(ImageFormat::Jpeg),
+            "bmp" => Some(ImageFormat::Bmp),
+            "webp" => Some(ImageFormat::WebP),
+            "tif" | "tiff" => Some(ImageFormat::Tiff),
+            _ => None,
+        }
+    }
+
+    /// Whether `save_image` can embed `ImageInfo` in this format.
+    pub fn has_metadata(&self) -> bool {
+        *self != ImageFormat::Bmp
+    }
+}
+
+/// What is known about a decoded picture, stored alongside it so an archive stays searchable.
+#[derive(Clone, Default)]
+#[derive(Serialize)]
+pub struct ImageInfo {
+    pub mode: String,
+    pub vis: Option<u8>,
+    /// Callsign read from the FSK ID.
+    pub callsign: Option<String>,
+    pub received: Option<chrono::DateTime<chrono::Utc>>,
+    /// Name of the recording the picture was decoded from.
+    pub source: Option<String>,
+    pub freq_offset: Option<f32>,
+    /// Clock error of the recording, measured from its line period, in parts per million.
+    pub clock_error_ppm: Option<f32>,
+    pub snr_db: Option<f32>,
+}
+
+impl ImageInfo {
+    /// Collects what the decoder found out about the picture it decoded from `source`.
+    pub fn new(mode: &Mode, decoded: &DecodedImage, source: Option<&Path>, snr_db: Option<f32>) -> Self {
+        Self {
+            mode: mode.to_string(),
+            vis: decoded.vis,
+            callsign: decoded.fsk_id.clone(),
+            // The recording is finished writing when reception ends.
+            received: source.and_then(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok()).map(chrono::DateTime::from),
+            source: source.and_then(Path::file_name).map(|name| name.to_string_lossy().into_owned()),
+            freq_offset: decoded.freq_offset,
+            clock_error_ppm: decoded.line_time.zip(mode.line_time()).map(|(measured, nominal)| (measured / nominal - 1.0) * 1e6),
+            snr_db,
+        }
+    }
+
+    /// Name and value of every field that is known, in the order they are written.
+    pub fn fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = vec![("Mode", self.mode.clone())];
+        if let Some(vis) = self.vis {
+            fields.push(("VIS", vis.to_string()));
+        }
+        if let Some(callsign) = &self.callsign {
+            fields.push(("Callsign", callsign.clone()));
+        }
+        if let Some(received) = self.received {
+            fields.push(("Received", received.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)));
+        }
+        if let Some(source) = &self.source {
+            fields.push(("Source File", source.clone()));
+        }
+        if let Some(offset) = self.freq_offset {
+            fields.push(("Frequency Offset", format!("{:+.1} Hz", offset)));
+        }
+        if let Some(clock_error) = self.clock_error_ppm {
+            fields.push(("Clock Error", format!("{:+.0} ppm", clock_error)));
+        }
+        if let Some(snr) = self.snr_db {
+            fields.push(("SNR", format!("{:.1} dB", snr)));
+        }
+        fields
+    }
+
+    // Every field on one line, for formats that only have a single description tag.
+    fn description(&self) -> String {
+        self.fields().iter().map(|(name, value)| format!("{name}: {value}")).collect::<Vec<_>>().join("; ")
+    }
+
+    fn exif_date(&self) -> Option<String> {
+        self.received.map(|received| received.format("%Y:%m:%d %H:%M:%S").to_string())
+    }
+}
+
+const SOFTWARE: &str = "73-SSTV";
+
+// Minimal little endian EXIF block holding the description, software and date tags of IFD0.
+fn exif(info: &ImageInfo) -> Vec<u8> {
+    let mut entries: Vec<(u16, String)> = vec![(0x010E, info.description()), (0x0131, SOFTWARE.to_string())];
+    if let Some(date) = info.exif_date() {
+        entries.push((0x0132, date));
+    }
+
+    let mut block: Vec<u8> = b"II\x2A\x00\x08\x00\x00\x00".to_vec();
+    let mut data: Vec<u8> = Vec::new();
+    let data_start = 8 + 2 + 12 * entries.len() + 4;
+
+    block.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    for (tag, text) in &entries {
+        let mut value = text.as_bytes().to_vec();
+        value.push(0);
+
+        block.extend_from_slice(&tag.to_le_bytes());
+        block.extend_from_slice(&2u16.to_le_bytes());
+        block.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        if value.len() <= 4 {
+            value.resize(4, 0);
+            block.extend_from_slice(&value);
+        } else {
+            block.extend_from_slice(&((data_start + data.len()) as u32).to_le_bytes());
+            data.extend_from_slice(&value);
+            // Values start on a word boundary.
+            if data.len() % 2 == 1 {
+                data.push(0);
+            }
+        }
+    }
+    block.extend_from_slice(&0u32.to_le_bytes());
+    block.extend_from_slice(&data);
+    block
+}
+
+/// Writes the picture in the chosen format, with `info` in PNG text chunks, EXIF for JPEG and WebP,
+/// or the TIFF description tags. BMP has nowhere to keep it.
+pub fn save_image(path: &Path, image_data: &RgbImage, format: &ImageFormat, info: &ImageInfo) -> Result<()> {
+    write_image(BufWriter::new(File::create(path)?), image_data, format, info)
+}
+
+/// Writes the picture as `save_image` does, to any seekable writer; TIFF needs to go back to fill in offsets.
+pub fn write_image(writer: impl Write + Seek, image_data: &RgbImage, format: &ImageFormat, info: &ImageInfo) -> Result<()> {
+    let (width, height) = image_data.dimensions();
+    let rgb = image_data.as_raw();
+
+    match format {
+        ImageFormat::Png => {
+            let mut encoder = png::Encoder::new(writer, width, height);
+            encoder.set_color(png::ColorType::Rgb);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder.add_text_chunk(String::from("Software"), SOFTWARE.to_string())?;
+            for (name, value) in info.fields() {
+                encoder.add_text_chunk(name.to_string(), value)?;
+            }
+            let mut writer = encoder.write_header()?;
+            writer.write_image_data(rgb)?;
+            writer.finish()?;
+        }
+        ImageFormat::Jpeg => {
+            let mut encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(writer, 95);
+            encoder.set_exif_metadata(exif(info)).map_err(image::ImageError::Unsupported)?;
+            encoder.write_image(rgb, width, height, image::ExtendedColorType::Rgb8)?;
+        }
+        ImageFormat::WebP => {
+            let mut encoder = image::codecs::webp::WebPEncoder::new_lossless(writer);
+            encoder.set_exif_metadata(exif(info)).map_err(image::ImageError::Unsupported)?;
+            encoder.write_image(rgb, width, height, image::ExtendedColorType::Rgb8)?;
+        }
+        ImageFormat::Bmp => {
+            let mut writer = writer;
+            image::codecs::bmp::BmpEncoder::new(&mut writer).write_image(rgb, width, height, image::ExtendedColorType::Rgb8)?;
+        }
+        ImageFormat::Tiff => {
+            let mut encoder = tiff::encoder::TiffEncoder::new(writer)?;
+            let mut tiff_image = encoder.new_image::<tiff::encoder::colortype::RGB8>(width, height)?;
+            let tags = tiff_image.encoder();
+            tags.write_tag(tiff::tags::Tag::ImageDescription, info.description().as_str())?;
+            tags.write_tag(tiff::tags::Tag::Software, SOFTWARE)?;
+            if let Some(date) = info.exif_date() {
+                tags.write_tag(tiff::tags::Tag::DateTime, date.as_str())?;
+            }
+            tiff_image.write_data(rgb)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Everything written to the JSON file saved next to an auto-saved picture.
+#[derive(Serialize)]
+pub struct DecodeStats {
+    #[serde(flatten)]
+    pub info: ImageInfo,
+    pub width: usize,
+    pub lines: usize,
+    /// Mean confidence of the line syncs, from 0 to 1.
+    pub mean_sync_confidence: f32,
+    /// Lines whose sync confidence was below one half.
+    pub weak_lines: usize,
+    pub demodulator: String,
+    pub post_filter: String,
+    pub flywheel: bool,
+    pub afc: bool,
+}
+
+impl DecodeStats {
+    pub fn new(info: ImageInfo, image_data: &RgbImage, sync_confidence: &[f32], demod_method: &DemodMethod, settings: &DecodeSettings) -> Self {
+        Self {
+            info,
+            width: image_data.width() as usize,
+            lines: image_data.height() as usize,
+            mean_sync_confidence: sync_confidence.iter().sum::<f32>() / sync_confidence.len().max(1) as f32,
+            weak_lines: sync_confidence.iter().filter(|&&c| c < 0.5).count(),
+            demodulator: demod_method.to_string(),
+            post_filter: settings.post_filter.to_string(),
+            flywheel: settings.flywheel,
+            afc: settings.afc,
+        }
+    }
+}
+
+/// Writes every completed decode into `directory` without asking where.
+pub struct AutoSave {
+    pub enabled: bool,
+    pub directory: Option<PathBuf>,
+    /// File name with `{date}`, `{time}`, `{mode}`, `{callsign}`, `{vis}` and `{source}` placeholders;
+    /// its extension picks the format.
+    pub pattern: String,
+    pub thumbnail: bool,
+    pub sidecar: bool,
+}
+
+impl Default for AutoSave {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            directory: None,
+            pattern: String::from("{date}_{time}_{mode}_{callsign}.png"),
+            thumbnail: true,
+            sidecar: true,
+        }
+    }
+}
+
+const THUMBNAIL_WIDTH: u32 = 160;
+
+// Keeps a placeholder value safe to use in a file name on every platform.
+fn file_safe(value: &str) -> String {
+    value.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' }).collect()
+}
+
+impl AutoSave {
+    /// File name for a picture described by `info`, before any clash with an existing file is resolved.
+    pub fn file_name(&self, info: &ImageInfo) -> String {
+        let received = info.received.unwrap_or_else(chrono::Utc::now);
+        let source = info.source.as_deref().map(|source| Path::new(source).file_stem().unwrap_or_default().to_string_lossy().into_owned());
+        self.pattern
+            .replace("{date}", &received.format("%Y-%m-%d").to_string())
+            .replace("{time}", &received.format("%H%M%S").to_string())
+            .replace("{mode}", &file_safe(&info.mode))
+            .replace("{callsign}", &file_safe(info.callsign.as_deref().unwrap_or("unknown")))
+            .replace("{vis}", &info.vis.map(|vis| vis.to_string()).unwrap_or_default())
+            .replace("{source}", &file_safe(source.as_deref().unwrap_or("")))
+    }
+
+    /// Saves the picture, its thumbnail and its sidecar, returning where the picture went.
+    pub fn save(&self, image_data: &RgbImage, stats: &DecodeStats) -> Result<PathBuf> {
+        let Some(directory) = &self.directory else {
+            return Err(Error::Io(std::io::Error::new(std::io::ErrorKind::NotFound, "no auto save folder chosen")))
+        };
+        std::fs::create_dir_all(directory)?;
+
+        let name = PathBuf::from(self.file_name(&stats.info));
+        let format = name.extension().and_then(|ext| ImageFormat::from_extension(&ext.to_string_lossy())).unwrap_or(ImageFormat::Png);
+        let stem = name.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+
+        // Never overwrite an earlier picture that happens to get the same name.
+        let mut path = directory.join(format!("{stem}.{}", format.extension()));
+        let mut copy = 1;
+        while path.exists() {
+            copy += 1;
+            path = directory.join(format!("{stem}_{copy}.{}", format.extension()));
+        }
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+
+        save_image(&path, image_data, &format, &stats.info)?;
+
+        if self.thumbnail {
+            let (width, height) = image_data.dimensions();
+            let thumb_height = u32::max(1, (height as f32 * THUMBNAIL_WIDTH as f32 / width.max(1) as f32).round() as u32);
+            image::imageops::thumbnail(image_data, THUMBNAIL_WIDTH, thumb_height)
+                .save(directory.join(format!("{stem}_thumb.png")))?;
+        }
+
+        if self.sidecar {
+            std::fs::write(directory.join(format!("{stem}.json")), serde_json::to_string_pretty(stats)?)?;
+        }
+
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn info() -> ImageInfo {
+        ImageInfo {
+            mode: String::from("Martin M1"),
+            vis: Some(44),
+            callsign: Some(String::from("W1AW/P")),
+            received: Some(chrono::Utc.with_ymd_and_hms(2024, 5, 6, 12, 34, 56).unwrap()),
+            source: Some(String::from("pass 3.wav")),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn file_name_fills_the_pattern() {
+        let auto_save = AutoSave { pattern: String::from("{date}_{time}_{mode}_{callsign}_{vis}_{source}.jpg"), ..Default::default() };
+        assert_eq!(auto_save.file_name(&info()), "2024-05-06_123456_Martin_M1_W1AW_P_44_pass_3.jpg");
+
+        // Whatever the decoder could not tell is left blank, apart from the callsign.
+        let unknown = ImageInfo { vis: None, callsign: None, source: None, ..info() };
+        assert_eq!(auto_save.file_name(&unknown), "2024-05-06_123456_Martin_M1_unknown__.jpg");
+    }
+
+    #[test]
+    fn stats_summarise_the_syncs() {
+        let image = RgbImage::new(320, 255);
+        let settings = DecodeSettings { flywheel: false, ..Default::default() };
+        let stats = DecodeStats::new(info(), &image, &[1.0, 0.4, 0.7, 0.2], &DemodMethod::Pll, &settings);
+        assert_eq!((stats.width, stats.lines, stats.weak_lines), (320, 255, 2));
+        assert!((stats.mean_sync_confidence - 0.575).abs() < 1e-6);
+
+        // The picture's details sit at the top level of the sidecar, next to the decode's.
+        let json = serde_json::to_value(&stats).unwrap();
+        assert_eq!(json["mode"], "Martin M1");
+        assert_eq!(json["callsign"], "W1AW/P");
+        assert_eq!(json["demodulator"], "PLL");
+        assert_eq!(json["post_filter"], "Low Pass");
+        assert_eq!(json["flywheel"], false);
+
+        let empty = DecodeStats::new(info(), &image, &[], &DemodMethod::Pll, &settings);
+        assert_eq!(empty.mean_sync_confidence, 0.0);
+    }
+
+    #[test]
+    fn saves_never_overwrite_each_other() {
+        let directory = std::env::temp_dir().join(format!("sstv-autosave-{}", std::process::id()));
+        let auto_save = AutoSave { enabled: true, directory: Some(directory.clone()), ..Default::default() };
+        let image = RgbImage::new(320, 255);
+        let stats = DecodeStats::new(info(), &image, &[1.0], &DemodMethod::Pll, &DecodeSettings::default());
+
+        let first = auto_save.save(&image, &stats);
+        let second = auto_save.save(&image, &stats);
+        let files = ["2024-05-06_123456_Martin_M1_W1AW_P_thumb.png", "2024-05-06_123456_Martin_M1_W1AW_P_2.json"]
+            .map(|name| directory.join(name).exists());
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(first.unwrap(), directory.join("2024-05-06_123456_Martin_M1_W1AW_P.png"));
+        assert_eq!(second.unwrap(), directory.join("2024-05-06_123456_Martin_M1_W1AW_P_2.png"));
+        assert_eq!(files, [true, true]);
+    }
+}
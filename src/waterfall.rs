@@ -0,0 +1,89 @@
+use image::{Rgb, RgbImage};
+use num_complex::c32;
+use crate::fft;
+
+/// Tones worth finding at a glance: sync, black, the leader and VIS centre, and white.
+pub const MARKERS: [f32; 4] = [1200.0, 1500.0, 1900.0, 2300.0];
+
+/// Highest frequency shown, which leaves room either side of the SSTV band.
+pub const MAX_FREQUENCY: f32 = 3000.0;
+
+// Bin width the window is sized for; narrow enough to tell the tones apart.
+const RESOLUTION: f32 = 20.0;
+
+// Upper bound on the rows of a long recording, which are spread out to fit.
+const MAX_ROWS: usize = 2000;
+
+// Levels this far below the loudest bin are drawn black.
+const DYNAMIC_RANGE_DB: f32 = 60.0;
+
+/// Short time spectrum of a recording, one row of dB levels per step, earliest first.
+pub struct Waterfall {
+    pub rows: Vec<Vec<f32>>,
+    /// Width of each bin in Hz; bin 0 is 0 Hz.
+    pub bin_width: f32,
+    /// Length of the recording in seconds, which the rows cover evenly.
+    pub duration: f32,
+}
+
+impl Waterfall {
+    pub fn new(samples: &[f32], sample_rate: u32) -> Self {
+        let window_len = ((sample_rate as f32 / RESOLUTION) as usize).next_power_of_two();
+        let hop = usize::max(window_len / 2, samples.len() / MAX_ROWS);
+        let bin_width = sample_rate as f32 / window_len as f32;
+        let bins = ((MAX_FREQUENCY / bin_width).ceil() as usize + 1).min(window_len / 2);
+
+        // Hann window, so strong tones do not smear across the neighbouring bins.
+        let window: Vec<f32> = (0..window_len)
+            .map(|n| 0.5 - 0.5 * (std::f32::consts::TAU * n as f32 / window_len as f32).cos())
+            .collect();
+
+        let rows = (0..samples.len().saturating_sub(window_len) / hop + 1)
+            .map(|row| {
+                let frame = (0..window_len)
+                    .map(|n| c32(samples.get(row * hop + n).copied().unwrap_or(0.0) * window[n], 0.0))
+                    .collect();
+                fft::fft(frame).iter().take(bins).map(|z| 10.0 * (z.norm_sqr() + 1e-12).log10()).collect()
+            })
+            .collect();
+
+        Self { rows, bin_width, duration: samples.len() as f32 / sample_rate as f32 }
+    }
+
+    /// Frequency across, time down, with the loudest bin white.
+    pub fn to_image(&self) -> RgbImage {
+        let width = self.rows.first().map_or(0, Vec::len) as u32;
+        let loudest = self.rows.iter().flatten().copied().fold(f32::NEG_INFINITY, f32::max);
+        RgbImage::from_fn(width, self.rows.len() as u32, |x, y| {
+            heat((self.rows[y as usize][x as usize] - loudest) / DYNAMIC_RANGE_DB + 1.0)
+        })
+    }
+}
+
+// Black through blue, red and yellow to white as `level` goes from 0 to 1.
+fn heat(level: f32) -> Rgb<u8> {
+    let level = level.clamp(0.0, 1.0) * 4.0;
+    let ramp = |from: f32| ((level - from).clamp(0.0, 1.0) * 255.0) as u8;
+    match level {
+        l if l < 1.0 => Rgb([0, 0, ramp(0.0)]),
+        l if l < 2.0 => Rgb([ramp(1.0), 0, 255 - ramp(1.0)]),
+        l if l < 3.0 => Rgb([255, ramp(2.0), 0]),
+        _ => Rgb([255, 255, ramp(3.0)]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tone_lands_in_its_bin() {
+        let samples: Vec<f32> = (0..44100).map(|n| (std::f32::consts::TAU * 1900.0 * n as f32 / 44100.0).sin()).collect();
+        let waterfall = Waterfall::new(&samples, 44100);
+        assert!(waterfall.rows.len() > 1);
+        for row in &waterfall.rows {
+            let peak = row.iter().enumerate().max_by(|a, b| a.1.total_cmp(b.1)).unwrap().0;
+            assert!((peak as f32 * waterfall.bin_width - 1900.0).abs() <= waterfall.bin_width);
+        }
+    }
+}